@@ -0,0 +1,173 @@
+//! Running a [`VM`] inside an async runtime such as tokio, with program I/O
+//! going through [`AsyncRead`] and [`AsyncWrite`] instead of blocking a
+//! runtime thread.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::cell::WhitespaceInt;
+use crate::error::{IoContext, Result};
+use crate::interpreter::{FedInput, StepOutcome, VM};
+use crate::parser::Instruction;
+
+/// Number of executed instructions between two yields to the runtime, so a
+/// long computation doesn't starve the other tasks.
+pub const YIELD_INTERVAL: u64 = 1024;
+
+/// Bytes read from the reader at once.
+const READ_CHUNK: usize = 4096;
+
+impl<T: WhitespaceInt> VM<T> {
+    /// Like [`VM::execute`], but program input is read from `reader` and
+    /// output written to `writer` asynchronously, and the runtime gets control
+    /// back every [`YIELD_INTERVAL`] instructions.
+    ///
+    /// Input is read a chunk at a time whenever the program needs more of it,
+    /// and output is flushed before every read, so the program can be driven
+    /// interactively. The VM's own [`VM::host_io`] is put back afterwards.
+    pub async fn execute_async<R, W>(
+        &mut self,
+        instructions: &[Instruction<T>],
+        mut reader: R,
+        mut writer: W,
+    ) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let host_io = std::mem::replace(&mut self.host_io, Box::new(FedInput::default()));
+
+        let result = self.run_async(instructions, &mut reader, &mut writer).await;
+
+        self.host_io = host_io;
+
+        result
+    }
+
+    async fn run_async<R, W>(
+        &mut self,
+        instructions: &[Instruction<T>],
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        self.load_labels(instructions);
+
+        let mut chunk = vec![0; READ_CHUNK];
+        // the end of a character split across two reads
+        let mut partial = Vec::new();
+
+        loop {
+            let outcome = self.step(instructions);
+
+            let output = self.fed_input().map(FedInput::take_output);
+            if let Some(text) = output.filter(|text| !text.is_empty()) {
+                writer
+                    .write_all(text.as_bytes())
+                    .await
+                    .io_context("writing output")?;
+            }
+
+            match outcome? {
+                StepOutcome::Continue => {
+                    if self.steps.is_multiple_of(YIELD_INTERVAL) {
+                        YieldNow(false).await;
+                    }
+                }
+                StepOutcome::Halted => break,
+                StepOutcome::NeedsInput => {
+                    writer.flush().await.io_context("writing output")?;
+
+                    let read = reader.read(&mut chunk).await.io_context("reading input")?;
+
+                    if read == 0 {
+                        self.feed_input(&String::from_utf8_lossy(&partial));
+                        partial.clear();
+                        self.close_input();
+                    } else {
+                        partial.extend_from_slice(&chunk[..read]);
+                        let valid = match std::str::from_utf8(&partial) {
+                            Ok(text) => text.len(),
+                            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+                            // not UTF-8 at all; read it as replacement characters
+                            Err(_) => partial.len(),
+                        };
+                        let text: Vec<u8> = partial.drain(..valid).collect();
+                        self.feed_input(&String::from_utf8_lossy(&text));
+                    }
+                }
+            }
+        }
+
+        writer.flush().await.io_context("writing output")
+    }
+}
+
+/// Returns `Pending` once, so that the runtime can run other tasks.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::VM;
+    use crate::parser::Instruction;
+
+    #[tokio::test]
+    async fn execute_async() {
+        let program = [
+            Instruction::Push(0),
+            Instruction::ReadNumber,
+            Instruction::Push(0),
+            Instruction::HeapRetrieve,
+            Instruction::OutputNumber,
+            Instruction::Push(1),
+            Instruction::ReadChar,
+            Instruction::Push(2),
+            Instruction::ReadChar,
+            Instruction::Push(2),
+            Instruction::HeapRetrieve,
+            Instruction::OutputChar,
+            Instruction::Push(1),
+            Instruction::HeapRetrieve,
+            Instruction::OutputChar,
+            Instruction::Push(2_000),
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Push(1),
+            Instruction::Substract,
+            Instruction::Duplicate,
+            Instruction::JumpIfZero("\t".to_string()),
+            Instruction::Jump(" ".to_string()),
+            Instruction::MarkLocation("\t".to_string()),
+            Instruction::EndProgram,
+        ];
+        let mut vm: VM = VM::new();
+        let mut output = Vec::new();
+
+        vm.execute_async(&program, &b"42\n\xc3\xa9!"[..], &mut output)
+            .await
+            .unwrap();
+
+        assert_eq!(output, "42!é".as_bytes());
+        assert_eq!(vm.stack, vec![0]);
+        assert!(vm.fed_input().is_none());
+    }
+}
@@ -0,0 +1,353 @@
+use std::fmt::{Debug, Display};
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
+
+/// What arithmetic does when a result doesn't fit in a cell.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum OverflowPolicy {
+    /// Stop the program with an error.
+    #[default]
+    Trap,
+    /// Wrap around in two's complement.
+    Wrap,
+    /// Clamp to the smallest or largest value.
+    Saturate,
+}
+
+/// How Divide and Modulo round when an operand is negative.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DivisionSemantics {
+    /// Round the quotient towards negative infinity, so the remainder has the
+    /// sign of the divisor, like the Haskell reference implementation.
+    #[default]
+    Floor,
+    /// Round the quotient towards zero, so the remainder has the sign of the
+    /// dividend, like Rust's `/` and `%`.
+    Truncate,
+}
+
+/// Serialization bounds that cells need with the `serde` feature.
+#[cfg(feature = "serde")]
+pub trait SerdeCell: serde::Serialize + serde::de::DeserializeOwned {}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> SerdeCell for T {}
+
+/// Serialization bounds that cells need with the `serde` feature.
+#[cfg(not(feature = "serde"))]
+pub trait SerdeCell {}
+
+#[cfg(not(feature = "serde"))]
+impl<T> SerdeCell for T {}
+
+/// Integer type used for stack and heap cells.
+pub trait WhitespaceInt:
+    Clone
+    + PartialEq
+    + PartialOrd
+    + Debug
+    + Display
+    + FromStr
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Send
+    + Sync
+    + SerdeCell
+    + 'static
+{
+    fn zero() -> Self;
+
+    fn from_char(chr: char) -> Self;
+
+    fn from_usize(value: usize) -> Option<Self>;
+
+    fn to_usize(&self) -> Option<usize>;
+
+    fn to_u32(&self) -> Option<u32>;
+
+    /// Builds a literal from its binary digits, most significant first.
+    ///
+    /// Returns `None` if the value doesn't fit in the cell.
+    fn from_bits(negative: bool, bits: &[bool]) -> Option<Self>;
+
+    /// The sign and binary digits of the value, most significant first and
+    /// without leading zeros, so no digits at all for 0.
+    fn to_bits(&self) -> (bool, Vec<bool>);
+
+    /// Returns `None` on overflow with [`OverflowPolicy::Trap`].
+    fn add_with(&self, rhs: &Self, policy: OverflowPolicy) -> Option<Self>;
+
+    fn sub_with(&self, rhs: &Self, policy: OverflowPolicy) -> Option<Self>;
+
+    fn mul_with(&self, rhs: &Self, policy: OverflowPolicy) -> Option<Self>;
+
+    fn checked_div(&self, rhs: &Self) -> Option<Self>;
+
+    fn checked_rem(&self, rhs: &Self) -> Option<Self>;
+
+    /// Divides with the given rounding, `None` on a zero divisor or overflow.
+    fn div_with(&self, rhs: &Self, semantics: DivisionSemantics) -> Option<Self> {
+        let quotient = self.checked_div(rhs)?;
+
+        match semantics {
+            DivisionSemantics::Floor if self.rounds_up(rhs)? => {
+                Some(quotient - Self::from_usize(1)?)
+            }
+            _ => Some(quotient),
+        }
+    }
+
+    fn rem_with(&self, rhs: &Self, semantics: DivisionSemantics) -> Option<Self> {
+        let remainder = self.checked_rem(rhs)?;
+
+        match semantics {
+            DivisionSemantics::Floor if self.rounds_up(rhs)? => Some(remainder + rhs.clone()),
+            _ => Some(remainder),
+        }
+    }
+
+    /// Whether truncating division rounded the quotient up, i.e. there is a
+    /// remainder and the operands have different signs.
+    fn rounds_up(&self, rhs: &Self) -> Option<bool> {
+        let remainder = self.checked_rem(rhs)?;
+
+        Some(!remainder.is_zero() && remainder.is_negative() != rhs.is_negative())
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < Self::zero()
+    }
+
+    fn to_char(&self) -> Option<char> {
+        self.to_u32().and_then(char::from_u32)
+    }
+}
+
+macro_rules! impl_primitive {
+    ($($ty:ty),*) => {$(
+        impl WhitespaceInt for $ty {
+            fn zero() -> Self {
+                0
+            }
+
+            fn from_char(chr: char) -> Self {
+                chr as Self
+            }
+
+            fn from_usize(value: usize) -> Option<Self> {
+                value.try_into().ok()
+            }
+
+            fn to_usize(&self) -> Option<usize> {
+                (*self).try_into().ok()
+            }
+
+            fn to_u32(&self) -> Option<u32> {
+                (*self).try_into().ok()
+            }
+
+            fn from_bits(negative: bool, bits: &[bool]) -> Option<Self> {
+                let mut value: Self = 0;
+
+                for bit in bits {
+                    // accumulate on the negative side so that MIN fits
+                    value = value.checked_mul(2)?;
+                    if *bit {
+                        value = value.checked_sub(1)?;
+                    }
+                }
+
+                if negative {
+                    Some(value)
+                } else {
+                    value.checked_neg()
+                }
+            }
+
+            fn to_bits(&self) -> (bool, Vec<bool>) {
+                let digits = format!("{:b}", self.unsigned_abs());
+                let bits = digits.trim_start_matches('0').chars().map(|digit| digit == '1');
+
+                (*self < 0, bits.collect())
+            }
+
+            fn add_with(&self, rhs: &Self, policy: OverflowPolicy) -> Option<Self> {
+                match policy {
+                    OverflowPolicy::Trap => self.checked_add(*rhs),
+                    OverflowPolicy::Wrap => Some(self.wrapping_add(*rhs)),
+                    OverflowPolicy::Saturate => Some(self.saturating_add(*rhs)),
+                }
+            }
+
+            fn sub_with(&self, rhs: &Self, policy: OverflowPolicy) -> Option<Self> {
+                match policy {
+                    OverflowPolicy::Trap => self.checked_sub(*rhs),
+                    OverflowPolicy::Wrap => Some(self.wrapping_sub(*rhs)),
+                    OverflowPolicy::Saturate => Some(self.saturating_sub(*rhs)),
+                }
+            }
+
+            fn mul_with(&self, rhs: &Self, policy: OverflowPolicy) -> Option<Self> {
+                match policy {
+                    OverflowPolicy::Trap => self.checked_mul(*rhs),
+                    OverflowPolicy::Wrap => Some(self.wrapping_mul(*rhs)),
+                    OverflowPolicy::Saturate => Some(self.saturating_mul(*rhs)),
+                }
+            }
+
+            fn checked_div(&self, rhs: &Self) -> Option<Self> {
+                <$ty>::checked_div(*self, *rhs)
+            }
+
+            fn checked_rem(&self, rhs: &Self) -> Option<Self> {
+                <$ty>::checked_rem(*self, *rhs)
+            }
+        }
+    )*};
+}
+
+impl_primitive!(i32, i64, i128);
+
+#[cfg(feature = "bignum")]
+mod bignum {
+    use num_bigint::{BigInt, Sign};
+    use num_traits::{ToPrimitive, Zero};
+
+    use super::{OverflowPolicy, WhitespaceInt};
+
+    impl WhitespaceInt for BigInt {
+        fn zero() -> Self {
+            Zero::zero()
+        }
+
+        fn from_char(chr: char) -> Self {
+            u32::from(chr).into()
+        }
+
+        fn from_usize(value: usize) -> Option<Self> {
+            Some(value.into())
+        }
+
+        fn to_usize(&self) -> Option<usize> {
+            ToPrimitive::to_usize(self)
+        }
+
+        fn to_u32(&self) -> Option<u32> {
+            ToPrimitive::to_u32(self)
+        }
+
+        fn from_bits(negative: bool, bits: &[bool]) -> Option<Self> {
+            let digits: Vec<u8> = bits.iter().map(|bit| u8::from(*bit)).collect();
+            let sign = if negative { Sign::Minus } else { Sign::Plus };
+
+            Some(BigInt::from_radix_be(sign, &digits, 2).unwrap_or_default())
+        }
+
+        fn to_bits(&self) -> (bool, Vec<bool>) {
+            let (sign, digits) = self.to_radix_be(2);
+            let bits = digits.into_iter().skip_while(|digit| *digit == 0);
+
+            (sign == Sign::Minus, bits.map(|digit| digit == 1).collect())
+        }
+
+        // big integers never overflow, whatever the policy
+        fn add_with(&self, rhs: &Self, _policy: OverflowPolicy) -> Option<Self> {
+            Some(self + rhs)
+        }
+
+        fn sub_with(&self, rhs: &Self, _policy: OverflowPolicy) -> Option<Self> {
+            Some(self - rhs)
+        }
+
+        fn mul_with(&self, rhs: &Self, _policy: OverflowPolicy) -> Option<Self> {
+            Some(self * rhs)
+        }
+
+        fn checked_div(&self, rhs: &Self) -> Option<Self> {
+            (!Zero::is_zero(rhs)).then(|| self / rhs)
+        }
+
+        fn checked_rem(&self, rhs: &Self) -> Option<Self> {
+            (!Zero::is_zero(rhs)).then(|| self % rhs)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitive_from_bits() {
+        assert_eq!(i32::from_bits(false, &[true, false, true]), Some(5));
+        assert_eq!(i32::from_bits(true, &[true, false, true]), Some(-5));
+        assert_eq!(i32::from_bits(false, &[]), Some(0));
+        assert_eq!(i32::from_bits(false, &[true; 31]), Some(i32::MAX));
+        assert_eq!(i32::from_bits(false, &[true; 32]), None);
+
+        let mut min = vec![false; 32];
+        min[0] = true;
+        assert_eq!(i32::from_bits(true, &min), Some(i32::MIN));
+        assert_eq!(i32::MIN.to_bits(), (true, min));
+        assert_eq!(5.to_bits(), (false, vec![true, false, true]));
+        assert_eq!(0.to_bits(), (false, vec![]));
+    }
+
+    #[test]
+    fn wider_primitives() {
+        assert_eq!(i64::from_bits(false, &[true; 32]), Some(u32::MAX.into()));
+        assert_eq!(i64::from_bits(false, &[true; 64]), None);
+        assert_eq!(
+            i128::from_bits(true, &[true; 64]),
+            Some(-i128::from(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn overflow_policies() {
+        assert_eq!(i32::MAX.add_with(&1, OverflowPolicy::Trap), None);
+        assert_eq!(i32::MAX.add_with(&1, OverflowPolicy::Wrap), Some(i32::MIN));
+        assert_eq!(
+            i32::MIN.sub_with(&1, OverflowPolicy::Saturate),
+            Some(i32::MIN)
+        );
+        assert_eq!(
+            i32::MAX.mul_with(&-2, OverflowPolicy::Saturate),
+            Some(i32::MIN)
+        );
+        assert_eq!(6.mul_with(&7, OverflowPolicy::Trap), Some(42));
+    }
+
+    #[test]
+    fn division_semantics() {
+        let floor = DivisionSemantics::Floor;
+        let truncate = DivisionSemantics::Truncate;
+
+        assert_eq!((-7).div_with(&2, floor), Some(-4));
+        assert_eq!((-7).rem_with(&2, floor), Some(1));
+        assert_eq!(7.div_with(&-2, floor), Some(-4));
+        assert_eq!(7.rem_with(&-2, floor), Some(-1));
+        assert_eq!((-8).div_with(&2, floor), Some(-4));
+        assert_eq!((-7).div_with(&2, truncate), Some(-3));
+        assert_eq!((-7).rem_with(&2, truncate), Some(-1));
+        assert_eq!(7.div_with(&0, floor), None);
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn bignum_from_bits() {
+        use num_bigint::BigInt;
+
+        let value = BigInt::from_bits(true, &[true; 100]).unwrap();
+        let expected: BigInt = (BigInt::from(1) << 100) - 1;
+        assert_eq!(value, -expected);
+        assert_eq!(value.to_bits(), (true, vec![true; 100]));
+        assert_eq!(BigInt::from_bits(false, &[]), Some(BigInt::from(0)));
+        assert_eq!(BigInt::from(0).to_bits(), (false, vec![]));
+    }
+}
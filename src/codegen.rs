@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use crate::cell::WhitespaceInt;
+use crate::lexer::{Alphabet, Token};
+use crate::parser::Instruction;
+
+/// Writes instructions back out as Whitespace source, the inverse of
+/// [`Parser`](crate::parser::Parser).
+///
+/// Numbers are written with their shortest encoding: a sign and then binary
+/// digits without leading zeros, with 0 written as a lone positive sign.
+///
+/// ```
+/// use whitespace::codegen::Generator;
+///
+/// let program: whitespace::Program = whitespace::ws_program![push(-2), printn, end];
+/// let source = Generator::new().generate(&program);
+/// assert_eq!(source, "  \t\t \n\t\n \t\n\n\n");
+///
+/// let parsed = whitespace::parse::<i32>(&source).unwrap();
+/// assert_eq!(parsed.instructions(), program.instructions());
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Generator {
+    alphabet: Alphabet,
+    minimize_labels: bool,
+}
+
+impl Generator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the program with the characters of `alphabet`.
+    pub fn alphabet(mut self, alphabet: Alphabet) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    /// Renames labels to the shortest distinct ones, giving the shortest to
+    /// the labels used most.
+    ///
+    /// Otherwise labels are written as they are, a space for each space and a
+    /// tab for any other character.
+    pub fn minimize_labels(mut self, minimize_labels: bool) -> Self {
+        self.minimize_labels = minimize_labels;
+        self
+    }
+
+    pub fn generate<T: WhitespaceInt>(&self, instructions: &[Instruction<T>]) -> String {
+        self.tokens(instructions)
+            .iter()
+            .map(|token| match token {
+                Token::Space => self.alphabet.space,
+                Token::Tab => self.alphabet.tab,
+                Token::LineFeed => self.alphabet.line_feed,
+            })
+            .collect()
+    }
+
+    pub fn tokens<T: WhitespaceInt>(&self, instructions: &[Instruction<T>]) -> Vec<Token> {
+        let renamed = if self.minimize_labels {
+            minimal_labels(instructions)
+        } else {
+            HashMap::new()
+        };
+        let mut tokens = Vec::new();
+
+        for instruction in instructions {
+            let (opcode, argument) = opcode(instruction);
+            tokens.extend(opcode.iter().cloned());
+
+            match argument {
+                Argument::None => {}
+                Argument::Number(number) => write_number(&mut tokens, number),
+                Argument::Label(label) => {
+                    let label = renamed.get(label).map_or(label, String::as_str);
+                    tokens.extend(label.chars().map(|chr| match chr {
+                        ' ' => Token::Space,
+                        _ => Token::Tab,
+                    }));
+                    tokens.push(Token::LineFeed);
+                }
+            }
+        }
+
+        tokens
+    }
+}
+
+/// Writes `instructions` as real whitespace, keeping their labels.
+pub fn generate<T: WhitespaceInt>(instructions: &[Instruction<T>]) -> String {
+    Generator::new().generate(instructions)
+}
+
+enum Argument<'a, T> {
+    None,
+    Number(&'a T),
+    Label(&'a str),
+}
+
+fn opcode<T>(instruction: &Instruction<T>) -> (&'static [Token], Argument<'_, T>) {
+    use Argument::{Label, Number};
+    use Token::{LineFeed as L, Space as S, Tab as T};
+
+    match instruction {
+        Instruction::Push(number) => (&[S, S], Number(number)),
+        Instruction::Duplicate => (&[S, L, S], Argument::None),
+        Instruction::Copy(number) => (&[S, T, S], Number(number)),
+        Instruction::Swap => (&[S, L, T], Argument::None),
+        Instruction::Discard => (&[S, L, L], Argument::None),
+        Instruction::Slide(number) => (&[S, T, L], Number(number)),
+        Instruction::Add => (&[T, S, S, S], Argument::None),
+        Instruction::Substract => (&[T, S, S, T], Argument::None),
+        Instruction::Multiply => (&[T, S, S, L], Argument::None),
+        Instruction::Divide => (&[T, S, T, S], Argument::None),
+        Instruction::Modulo => (&[T, S, T, T], Argument::None),
+        Instruction::HeapStore => (&[T, T, S], Argument::None),
+        Instruction::HeapRetrieve => (&[T, T, T], Argument::None),
+        Instruction::MarkLocation(label) => (&[L, S, S], Label(label)),
+        Instruction::Call(label) => (&[L, S, T], Label(label)),
+        Instruction::Jump(label) => (&[L, S, L], Label(label)),
+        Instruction::JumpIfZero(label) => (&[L, T, S], Label(label)),
+        Instruction::JumpIfNegative(label) => (&[L, T, T], Label(label)),
+        Instruction::EndSubroutine => (&[L, T, L], Argument::None),
+        Instruction::EndProgram => (&[L, L, L], Argument::None),
+        Instruction::OutputChar => (&[T, L, S, S], Argument::None),
+        Instruction::OutputNumber => (&[T, L, S, T], Argument::None),
+        Instruction::ReadChar => (&[T, L, T, S], Argument::None),
+        Instruction::ReadNumber => (&[T, L, T, T], Argument::None),
+    }
+}
+
+fn write_number<T: WhitespaceInt>(tokens: &mut Vec<Token>, number: &T) {
+    let (negative, bits) = number.to_bits();
+
+    tokens.push(if negative { Token::Tab } else { Token::Space });
+    tokens.extend(
+        bits.into_iter()
+            .map(|bit| if bit { Token::Tab } else { Token::Space }),
+    );
+    tokens.push(Token::LineFeed);
+}
+
+/// New names for every label, the most used first getting the shortest ones:
+/// ` `, `\t`, `  `, ` \t` and so on.
+fn minimal_labels<T>(instructions: &[Instruction<T>]) -> HashMap<&str, String> {
+    let mut uses: Vec<(&str, usize)> = Vec::new();
+
+    for label in instructions.iter().filter_map(Instruction::label) {
+        match uses.iter_mut().find(|(name, _)| *name == label) {
+            Some((_, count)) => *count += 1,
+            None => uses.push((label, 1)),
+        }
+    }
+
+    // stable, so ties keep the order the labels first appear in
+    uses.sort_by(|(_, left), (_, right)| right.cmp(left));
+
+    uses.into_iter()
+        .enumerate()
+        .map(|(i, (label, _))| (label, nth_label(i)))
+        .collect()
+}
+
+/// The `n`th label when ordered by length and then as binary numbers.
+fn nth_label(n: usize) -> String {
+    // labels of length k start at 2^k - 2, so n + 2 has one more digit than
+    // the label has characters
+    let digits = format!("{:b}", n + 2);
+
+    digits[1..]
+        .chars()
+        .map(|digit| if digit == '1' { '\t' } else { ' ' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::program::Program;
+
+    #[test]
+    fn round_trip() {
+        let source = "   \t \t\n   \n \t  \t\n\n  \t\t\n\t\n \t\t   \n \n\t\t\n\n\n\n";
+        let program: Program = Parser::new(Lexer::new(source).lex()).parse().unwrap();
+
+        assert_eq!(generate(&program), source);
+    }
+
+    #[test]
+    fn numbers() {
+        let program: Program = vec![
+            Instruction::Push(0),
+            Instruction::Push(-1),
+            Instruction::Copy(i32::MIN),
+            Instruction::Slide(6),
+        ]
+        .into();
+        let source = Generator::new()
+            .alphabet(Alphabet::LETTERS)
+            .generate(&program);
+
+        assert!(source.starts_with("SSSLSSTTLSTST"));
+        assert!(source.ends_with("LSTLSTTSL"));
+
+        let parsed: Program = Parser::new(Lexer::with_alphabet(source, Alphabet::LETTERS).lex())
+            .parse()
+            .unwrap();
+        assert_eq!(parsed.instructions(), program.instructions());
+    }
+
+    #[test]
+    fn minimal_labels() {
+        assert_eq!(
+            (0..6).map(nth_label).collect::<Vec<_>>(),
+            [" ", "\t", "  ", " \t", "\t ", "\t\t"]
+        );
+
+        let long = |bits: &str| bits.replace('0', " ").replace('1', "\t");
+        let program: Program = vec![
+            Instruction::MarkLocation(long("0110")),
+            Instruction::MarkLocation(long("1111")),
+            Instruction::Jump(long("1111")),
+        ]
+        .into();
+        let source = Generator::new()
+            .alphabet(Alphabet::LETTERS)
+            .minimize_labels(true)
+            .generate(&program);
+
+        assert_eq!(source, "LSSTLLSSSLLSLSL");
+    }
+}
@@ -0,0 +1,591 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use console::Style;
+use serde::{Serialize, Serializer};
+
+use crate::error::WsError;
+use crate::lexer::{LexerOptions, LineEndings};
+use crate::parser::Instruction;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Lint {
+    /// Non-significant whitespace (CR, NBSP, ...) that the lexer treats as a comment.
+    SuspiciousCharacters,
+    /// Labels that are marked but never jumped to or called.
+    UnusedLabels,
+    /// Distinct labels that only differ by leading spaces, which interpreters
+    /// reading labels as binary numbers take for the same label.
+    NearDuplicateLabels,
+    /// Constructs only some dialects accept, such as empty labels and
+    /// vertical tabs used as tokens.
+    DeprecatedConstructs,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 4] = [
+        Lint::SuspiciousCharacters,
+        Lint::UnusedLabels,
+        Lint::NearDuplicateLabels,
+        Lint::DeprecatedConstructs,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::SuspiciousCharacters => "suspicious-characters",
+            Lint::UnusedLabels => "unused-labels",
+            Lint::NearDuplicateLabels => "near-duplicate-labels",
+            Lint::DeprecatedConstructs => "deprecated-constructs",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Lint {
+    type Err = WsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Lint::ALL
+            .into_iter()
+            .find(|lint| lint.name() == s)
+            .ok_or_else(|| WsError::UnknownLint(s.to_string()))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    /// Byte offset from the start of the source.
+    pub offset: usize,
+}
+
+#[derive(Debug)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// Broad category of a diagnostic: the phase that produced it, or `lint` for
+/// warnings.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Parse,
+    Validation,
+    Runtime,
+    Io,
+    Lint,
+}
+
+/// What a diagnostic is about, shown in brackets after its message.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Code {
+    ParseError,
+    /// A number literal too large for the cells.
+    LiteralOverflow,
+    DuplicateLabel,
+    UndefinedLabel,
+    RuntimeError,
+    IoError,
+    Lint(Lint),
+}
+
+impl Code {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Code::ParseError => "parse-error",
+            Code::LiteralOverflow => "literal-overflow",
+            Code::DuplicateLabel => "duplicate-label",
+            Code::UndefinedLabel => "undefined-label",
+            Code::RuntimeError => "runtime-error",
+            Code::IoError => "io-error",
+            Code::Lint(lint) => lint.name(),
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        match self {
+            Code::ParseError | Code::LiteralOverflow => Kind::Parse,
+            Code::DuplicateLabel | Code::UndefinedLabel => Kind::Validation,
+            Code::RuntimeError => Kind::Runtime,
+            Code::IoError => Kind::Io,
+            Code::Lint(_) => Kind::Lint,
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for Code {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// A message reported to the user, independent of how it is rendered.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    /// Index of the instruction the diagnostic is about.
+    pub instruction: Option<usize>,
+    /// Label the diagnostic is about.
+    pub label: Option<String>,
+    /// Stack contents when a runtime error happened, bottom first.
+    pub stack: Option<Vec<String>>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span: None,
+            notes: Vec::new(),
+            instruction: None,
+            label: None,
+            stack: None,
+        }
+    }
+
+    pub fn from_warning(warning: Warning, severity: Severity) -> Self {
+        Self {
+            severity,
+            code: Code::Lint(warning.lint),
+            message: warning.message,
+            span: warning.span,
+            notes: Vec::new(),
+            instruction: None,
+            label: None,
+            stack: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_instruction(mut self, instruction: usize) -> Self {
+        self.instruction = Some(instruction);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_stack<T: fmt::Display>(mut self, stack: &[T]) -> Self {
+        self.stack = Some(stack.iter().map(ToString::to_string).collect());
+        self
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.code.kind()
+    }
+
+    /// The diagnostic as one line of JSON, for `--error-format json`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("diagnostics serialize to JSON")
+    }
+}
+
+/// How a [`Diagnostic`] is serialized, with its kind spelled out.
+#[derive(Serialize)]
+struct SerializedDiagnostic<'a> {
+    severity: Severity,
+    kind: Kind,
+    code: Code,
+    message: &'a str,
+    span: Option<Span>,
+    instruction: Option<usize>,
+    label: Option<&'a str>,
+    #[serde(serialize_with = "serialize_cells")]
+    stack: Option<&'a [String]>,
+    notes: &'a [String],
+}
+
+impl Serialize for Diagnostic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedDiagnostic {
+            severity: self.severity,
+            kind: self.kind(),
+            code: self.code,
+            message: &self.message,
+            span: self.span,
+            instruction: self.instruction,
+            label: self.label.as_deref(),
+            stack: self.stack.as_deref(),
+            notes: &self.notes,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Writes cells as numbers, falling back to strings for big integers that
+/// don't fit an `i128`.
+fn serialize_cells<S: Serializer>(
+    stack: &Option<&[String]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Cell<'a> {
+        Number(i128),
+        Big(&'a str),
+    }
+
+    stack
+        .map(|stack| {
+            stack
+                .iter()
+                .map(|cell| cell.parse().map_or(Cell::Big(cell), Cell::Number))
+                .collect::<Vec<_>>()
+        })
+        .serialize(serializer)
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.severity)?;
+        if let Some(Span { line, column, .. }) = self.span {
+            write!(f, "{line}:{column}: ")?;
+        }
+        write!(f, "{} [{}]", self.message, self.code)?;
+        for note in &self.notes {
+            write!(f, "\n  = note: {note}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders the source line containing `span` with a caret under the
+/// offending character, in the style of compiler diagnostics.
+///
+/// With `visualize`, spaces, tabs and the line feed are drawn as `S`, `T` and
+/// `L` and the comment text around them is dimmed; otherwise the line is
+/// shown as written, which suits programs written with letters.
+pub fn excerpt(source: &str, span: Span, visualize: bool, color: bool) -> String {
+    let style = |style: Style| style.force_styling(color);
+    let gutter = style(Style::new().blue().bold());
+    let marker = style(Style::new().cyan());
+    let comment = style(Style::new().dim());
+    let caret = style(Style::new().red().bold());
+
+    let offset = span.offset.min(source.len());
+    let start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |index| offset + index);
+
+    let mut line = String::new();
+    for chr in source[start..end].chars() {
+        let shown = match (chr, visualize) {
+            (' ', true) => marker.apply_to('S').to_string(),
+            ('\t', true) => marker.apply_to('T').to_string(),
+            ('\t', false) => " ".to_string(),
+            (chr, _) if chr.is_control() => comment.apply_to('\u{b7}').to_string(),
+            (chr, true) => comment.apply_to(chr).to_string(),
+            (chr, false) => chr.to_string(),
+        };
+        line.push_str(&shown);
+    }
+    if visualize && end < source.len() {
+        line.push_str(&marker.apply_to('L').to_string());
+    }
+
+    let number = span.line.to_string();
+    let blank = " ".repeat(number.len());
+    let bar = gutter.apply_to("|");
+    format!(
+        "{blank} {bar}\n{} {bar} {line}\n{blank} {bar} {}{}",
+        gutter.apply_to(&number),
+        " ".repeat(span.column.saturating_sub(1)),
+        caret.apply_to('^'),
+    )
+}
+
+#[derive(Debug, Default)]
+pub struct LintConfig {
+    levels: HashMap<Lint, Level>,
+    deny_warnings: bool,
+}
+
+impl LintConfig {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    pub fn deny_warnings(&mut self, deny: bool) {
+        self.deny_warnings = deny;
+    }
+
+    pub fn level(&self, lint: Lint) -> Level {
+        match self.levels.get(&lint).copied().unwrap_or(Level::Warn) {
+            Level::Warn if self.deny_warnings => Level::Deny,
+            level => level,
+        }
+    }
+}
+
+/// Looks for characters in real whitespace source that `options` make the
+/// lexer ignore although they look like whitespace, or read as tokens
+/// although only some dialects do.
+pub fn check_source(source: &str, options: &LexerOptions) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    let mut line_start = 0;
+    for (line, content) in source.split('\n').enumerate() {
+        for (column, (index, chr)) in content.char_indices().enumerate() {
+            let (lint, message) = match chr {
+                ' ' | '\t' => continue,
+                '\r' if options.line_endings == LineEndings::Any => continue,
+                '\u{b}' if options.vertical_tab.is_some() => (
+                    Lint::DeprecatedConstructs,
+                    "vertical tab read as a token, which most interpreters ignore".to_string(),
+                ),
+                chr if chr.is_whitespace() => (
+                    Lint::SuspiciousCharacters,
+                    format!(
+                        "character U+{:04X} looks like whitespace but is ignored",
+                        chr as u32
+                    ),
+                ),
+                _ => continue,
+            };
+            warnings.push(Warning {
+                lint,
+                message,
+                span: Some(Span {
+                    line: line + 1,
+                    column: column + 1,
+                    offset: line_start + index,
+                }),
+            });
+        }
+
+        line_start += content.len() + 1;
+    }
+
+    warnings
+}
+
+pub fn check_program<T>(instructions: &[Instruction<T>]) -> Vec<Warning> {
+    let referenced: HashSet<&String> = instructions
+        .iter()
+        .filter_map(|instr| match instr {
+            Instruction::Call(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpIfZero(label)
+            | Instruction::JumpIfNegative(label) => Some(label),
+            _ => None,
+        })
+        .collect();
+    // marked labels by their value as a binary number
+    let mut numbers: HashMap<&str, &String> = HashMap::new();
+    let mut warnings = Vec::new();
+
+    for (i, instr) in instructions.iter().enumerate() {
+        let Instruction::MarkLocation(label) = instr else {
+            continue;
+        };
+
+        if !referenced.contains(label) {
+            warnings.push(Warning {
+                lint: Lint::UnusedLabels,
+                message: format!("label {label:?} at instruction {i} is never used"),
+                span: None,
+            });
+        }
+        if label.is_empty() {
+            warnings.push(Warning {
+                lint: Lint::DeprecatedConstructs,
+                message: format!("empty label at instruction {i}, which some interpreters reject"),
+                span: None,
+            });
+        }
+        match numbers.get(label.trim_start_matches(' ')) {
+            Some(other) if *other != label => warnings.push(Warning {
+                lint: Lint::NearDuplicateLabels,
+                message: format!(
+                    "label {label:?} at instruction {i} only differs from {other:?} by leading \
+                     spaces, so some interpreters take them for the same label"
+                ),
+                span: None,
+            }),
+            Some(_) => {}
+            None => {
+                numbers.insert(label.trim_start_matches(' '), label);
+            }
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suspicious_characters() {
+        let warnings = check_source("  \t\r\n\u{a0}\n", &LexerOptions::default());
+
+        assert_eq!(warnings.len(), 2);
+        let span = |line, column, offset| {
+            Some(Span {
+                line,
+                column,
+                offset,
+            })
+        };
+        assert_eq!(warnings[0].span, span(1, 4, 3));
+        assert!(warnings[0].message.starts_with("character U+000D"));
+        assert_eq!(warnings[1].span, span(2, 1, 5));
+        assert!(warnings[1].message.starts_with("character U+00A0"));
+
+        let options = LexerOptions {
+            line_endings: LineEndings::Any,
+            vertical_tab: Some(crate::lexer::Token::Tab),
+        };
+        let warnings = check_source(" \r\n\u{b}\n", &options);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::DeprecatedConstructs);
+    }
+
+    #[test]
+    fn unused_labels() {
+        let instructions: Vec<Instruction> = vec![
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::MarkLocation("\t".to_string()),
+            Instruction::Jump(" ".to_string()),
+        ];
+
+        let warnings = check_program(&instructions);
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::UnusedLabels);
+    }
+
+    #[test]
+    fn label_dialects() {
+        let instructions: Vec<Instruction> = vec![
+            Instruction::MarkLocation("\t ".to_string()),
+            Instruction::MarkLocation("  \t ".to_string()),
+            Instruction::MarkLocation(String::new()),
+            Instruction::Jump("\t ".to_string()),
+            Instruction::Jump("  \t ".to_string()),
+            Instruction::Jump(String::new()),
+        ];
+
+        let lints: Vec<Lint> = check_program(&instructions)
+            .iter()
+            .map(|warning| warning.lint)
+            .collect();
+        assert_eq!(
+            lints,
+            [Lint::NearDuplicateLabels, Lint::DeprecatedConstructs]
+        );
+    }
+
+    #[test]
+    fn json() {
+        let diagnostic = Diagnostic::error(Code::ParseError, "bad \"sign\"\n").with_note("at 3");
+
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"severity":"error","kind":"parse","code":"parse-error","message":"bad \"sign\"\n","span":null,"instruction":null,"label":null,"stack":null,"notes":["at 3"]}"#
+        );
+
+        let diagnostic = Diagnostic::error(Code::RuntimeError, "label not found")
+            .with_instruction(4)
+            .with_label(" \t")
+            .with_stack(&[1, -2]);
+
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"severity":"error","kind":"runtime","code":"runtime-error","message":"label not found","span":null,"instruction":4,"label":" \t","stack":[1,-2],"notes":[]}"#
+        );
+
+        // too big for a number JSON readers agree on
+        let big = "9".repeat(40);
+        let diagnostic = Diagnostic::error(Code::RuntimeError, "").with_stack(&[&big]);
+        assert!(diagnostic
+            .to_json()
+            .contains(&format!(r#""stack":["{big}"]"#)));
+    }
+
+    #[test]
+    fn excerpts() {
+        let span = Span {
+            line: 2,
+            column: 5,
+            offset: 7,
+        };
+        assert_eq!(
+            excerpt("  \npush \t\n\n", span, true, false),
+            "  |\n2 | pushSTL\n  |     ^"
+        );
+
+        let span = Span {
+            line: 1,
+            column: 3,
+            offset: 2,
+        };
+        assert_eq!(
+            excerpt("SST\tL", span, false, false),
+            "  |\n1 | SST L\n  |   ^"
+        );
+    }
+
+    #[test]
+    fn levels() {
+        let mut config = LintConfig::default();
+        config.set(Lint::UnusedLabels, Level::Allow);
+        config.deny_warnings(true);
+
+        assert_eq!(config.level(Lint::UnusedLabels), Level::Allow);
+        assert_eq!(config.level(Lint::SuspiciousCharacters), Level::Deny);
+    }
+}
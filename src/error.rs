@@ -0,0 +1,126 @@
+use std::io;
+
+use crate::interpreter::{EndOfInput, FuelExhausted, InputPending, Interrupted, StackOverflow};
+use crate::parser::LiteralOverflow;
+
+/// Everything that can go wrong while loading or running a program.
+///
+/// Values from the stack or heap are kept as their decimal text, so the
+/// error doesn't depend on the cell type.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum WsError {
+    /// The tokens don't form a valid instruction.
+    #[error("{message}")]
+    ParseError {
+        /// Index of the token where the offending instruction starts.
+        offset: usize,
+        message: String,
+    },
+    #[error(transparent)]
+    LiteralOverflow(#[from] LiteralOverflow),
+    #[error("label {label:?} is marked at both instruction {first} and instruction {second}")]
+    DuplicateLabel {
+        label: String,
+        first: usize,
+        second: usize,
+    },
+    /// Every reference to a label that is never marked, as `"label" at
+    /// instruction N`.
+    #[error("undefined labels: {}", .0.join(", "))]
+    UndefinedLabels(Vec<String>),
+    #[error("{0}")]
+    InvalidAlphabet(String),
+    /// Text that isn't an instruction mnemonic.
+    #[error("invalid instruction `{0}`")]
+    InvalidInstruction(String),
+    #[error("unknown lint `{0}`")]
+    UnknownLint(String),
+
+    #[error("the program is empty")]
+    EmptyProgram,
+    #[error("ran off the end of the program after instruction {last} without reaching EndProgram")]
+    RanOffEnd { last: usize },
+    #[error(
+        "stack underflow: {opcode} at instruction {instruction} needs {needed} items \
+         but the stack has {available}"
+    )]
+    StackUnderflow {
+        instruction: usize,
+        opcode: String,
+        needed: usize,
+        available: usize,
+    },
+    #[error(transparent)]
+    StackOverflow(#[from] StackOverflow),
+    #[error("cannot copy item {index} of a stack of {len} items")]
+    InvalidCopy { index: String, len: usize },
+    #[error("cannot divide {dividend} (second from top) by zero (top of stack)")]
+    DivisionByZero { dividend: String },
+    #[error("cannot compute {dividend} (second from top) modulo zero (top of stack)")]
+    ModuloByZero { dividend: String },
+    #[error("integer overflow in {opcode} of {left} and {right} at instruction {instruction}")]
+    Overflow {
+        instruction: usize,
+        opcode: String,
+        left: String,
+        right: String,
+    },
+    #[error("{0} does not fit in a cell")]
+    CellOverflow(usize),
+    #[error("maximum call depth exceeded at instruction {instruction}")]
+    CallDepthExceeded { instruction: usize },
+    #[error("return outside of a subroutine")]
+    ReturnOutsideSubroutine,
+    #[error("label {0:?} not found")]
+    UnknownLabel(String),
+    #[error("invalid character {value} in stack")]
+    InvalidChar { value: String },
+    #[error("invalid character {value} at address {address}")]
+    InvalidHeapChar { value: String, address: usize },
+    #[error("invalid string length {0}")]
+    InvalidStringLength(String),
+    #[error("invalid address {0}")]
+    InvalidAddress(String),
+    /// The address is at or past [`VM::max_heap_size`](crate::VM::max_heap_size).
+    #[error("heap overflow at address {address}")]
+    HeapOutOfBounds { address: usize },
+    #[error("heap address overflow")]
+    AddressOverflow,
+    #[error("read of uninitialized heap address {address} at instruction {instruction}")]
+    UninitializedRead { address: usize, instruction: usize },
+    #[error("invalid number {0:?}")]
+    InvalidNumber(String),
+    #[error(transparent)]
+    EndOfInput(#[from] EndOfInput),
+    #[error(transparent)]
+    InputPending(#[from] InputPending),
+    #[error(transparent)]
+    FuelExhausted(#[from] FuelExhausted),
+    #[error(transparent)]
+    Interrupted(#[from] Interrupted),
+    /// The program was killed through its [`ExecutionHandle`](crate::handle::ExecutionHandle).
+    #[error("killed")]
+    Killed,
+    /// Displays as its context alone; the `io::Error` is its
+    /// [`source`](std::error::Error::source), printed by following the chain.
+    #[error("{context}")]
+    IoError {
+        context: &'static str,
+        #[source]
+        source: io::Error,
+    },
+}
+
+pub type Result<T, E = WsError> = std::result::Result<T, E>;
+
+/// Attaches what the VM was doing to an I/O error.
+pub(crate) trait IoContext<T> {
+    fn io_context(self, context: &'static str) -> Result<T>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+    fn io_context(self, context: &'static str) -> Result<T> {
+        self.map_err(|source| WsError::IoError { context, source })
+    }
+}
@@ -0,0 +1,182 @@
+//! Small programs shipped with the interpreter, to try it without looking
+//! for `.ws` files.
+//!
+//! The programs are written with the letters `S`, `T` and `L` standing for
+//! space, tab and line feed, so that they can carry comments; translate them
+//! with [`letters_to_whitespace`](crate::lexer::letters_to_whitespace).
+
+#[derive(Debug)]
+pub struct Example {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Program text, written with letters.
+    pub source: &'static str,
+    /// Options the program needs to run as intended.
+    pub args: &'static [&'static str],
+}
+
+pub const EXAMPLES: [Example; 5] = [
+    Example {
+        name: "hello",
+        description: "Print \"Hello, world!\"",
+        source: r#"
+SSSTSSTSSSL  push 'H'
+TLSS         print it
+SSSTTSSTSTL  push 'e'
+TLSS         print it
+SSSTTSTTSSL  push 'l'
+TLSS         print it
+SSSTTSTTSSL  push 'l'
+TLSS         print it
+SSSTTSTTTTL  push 'o'
+TLSS         print it
+SSSTSTTSSL   push ','
+TLSS         print it
+SSSTSSSSSL   push a space
+TLSS         print it
+SSSTTTSTTTL  push 'w'
+TLSS         print it
+SSSTTSTTTTL  push 'o'
+TLSS         print it
+SSSTTTSSTSL  push 'r'
+TLSS         print it
+SSSTTSTTSSL  push 'l'
+TLSS         print it
+SSSTTSSTSSL  push 'd'
+TLSS         print it
+SSSTSSSSTL   push '!'
+TLSS         print it
+SSSTSTSL     push a line feed
+TLSS         print it
+LLL          end
+"#,
+        args: &[],
+    },
+    Example {
+        name: "cat",
+        description: "Copy the input to the output",
+        source: r#"
+LSSSL  loop:
+SSSL   push address 0
+SLS    copy it
+TLTS   read a character into it
+TTT    fetch the character
+SLS    copy it
+LTTTL  negative at the end of input: jump to done
+TLSS   print it
+LSLSL  jump to loop
+LSSTL  done:
+LLL    end
+"#,
+        args: &["--eof", "minus-one"],
+    },
+    Example {
+        name: "count",
+        description: "Count from 1 to 10",
+        source: r#"
+SSSTL     push 1
+LSSSL     loop:
+SLS       copy the counter
+TLST      print it
+SSSTSTSL  push a line feed
+TLSS      print it
+SSSTL     push 1
+TSSS      add it to the counter
+SLS       copy the counter
+SSSTSTTL  push 11
+TSST      subtract
+LTSTL     zero once past 10: jump to done
+LSLSL     jump to loop
+LSSTL     done:
+LLL       end
+"#,
+        args: &[],
+    },
+    Example {
+        name: "gcd",
+        description: "Read two numbers and print their greatest common divisor",
+        source: r#"
+SSSL      push address 0
+TLTT      read a into it
+SSSTL     push address 1
+TLTT      read b into it
+SSSL      push address 0
+TTT       fetch a
+SSSTL     push address 1
+TTT       fetch b
+LSSSL     loop:
+SLS       copy b
+LTSTL     b is zero: jump to done
+SLT       swap to b, a
+STSSTL    copy b
+TSTT      a modulo b, leaving b, a % b
+LSLSL     jump to loop
+LSSTL     done:
+SLL       drop the zero
+TLST      print the divisor
+SSSTSTSL  push a line feed
+TLSS      print it
+LLL       end
+"#,
+        args: &[],
+    },
+    Example {
+        name: "fibonacci",
+        description: "Print the Fibonacci numbers below 1000",
+        source: r#"
+SSSL            push 0
+SSSTL           push 1
+LSSSL           loop:
+STSSTL          copy a
+TLST            print it
+SSSTSTSL        push a line feed
+TLSS            print it
+SLT             swap to b, a
+STSSTL          copy b
+TSSS            add, leaving b, a + b
+STSSTL          copy the next number to print
+SSSTTTTTSTSSSL  push 1000
+TSST            subtract
+LTTTL           below 1000: jump to more
+LLL             end
+LSSTL           more:
+LSLSL           jump to loop
+"#,
+        args: &[],
+    },
+];
+
+/// The example called `name`.
+pub fn find(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{EofBehavior, VM};
+    use crate::lexer::{letters_to_whitespace, Lexer};
+    use crate::parser::Parser;
+    use crate::program::Program;
+
+    fn run(name: &str, input: &'static str) -> String {
+        let source = letters_to_whitespace(find(name).unwrap().source);
+        let program: Program = Parser::new(Lexer::new(source).lex()).parse().unwrap();
+
+        let mut vm: VM = VM::new();
+        vm.eof = EofBehavior::MinusOne;
+        let run = vm.execute_capture(&program, input);
+        assert!(run.is_success(), "{name} failed: {:?}", run.error);
+
+        String::from_utf8(run.output).unwrap()
+    }
+
+    #[test]
+    fn outputs() {
+        assert_eq!(run("hello", ""), "Hello, world!\n");
+        assert_eq!(run("cat", "a\nb"), "a\nb");
+        assert_eq!(run("count", ""), "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+        assert_eq!(run("gcd", "48\n18\n"), "6\n");
+        assert!(run("fibonacci", "").ends_with("\n610\n987\n"));
+    }
+}
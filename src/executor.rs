@@ -0,0 +1,32 @@
+use crate::error::Result;
+use crate::heap::Heap;
+use crate::program::Program;
+
+/// Common interface over the execution backends.
+///
+/// Frontends (CLI, debugger, profiler) drive programs through this trait so
+/// they don't depend on how a backend actually runs the instructions.
+pub trait Executor {
+    /// Cell type of the stack and heap.
+    type Value;
+
+    /// Loads a program, replacing the previous one.
+    fn load(&mut self, program: Program<Self::Value>);
+
+    /// Executes a single instruction, returning `false` once the program has ended.
+    fn step(&mut self) -> Result<bool>;
+
+    /// Runs the loaded program until it ends or fails.
+    #[tracing::instrument(level = "debug", name = "execute", skip_all)]
+    fn run(&mut self) -> Result<()> {
+        while self.step()? {}
+
+        Ok(())
+    }
+
+    fn stack(&self) -> &[Self::Value];
+
+    fn heap(&self) -> &Heap<Self::Value>;
+
+    fn instruction_ptr(&self) -> usize;
+}
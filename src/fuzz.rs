@@ -0,0 +1,341 @@
+//! Random programs for stress-testing the VM.
+//!
+//! Every seed gives the same program and input, so a failure found by
+//! `whitespace fuzz` can be replayed from its seed alone:
+//!
+//! ```
+//! use whitespace::fuzz::{self, Case, Options};
+//!
+//! let case = Case::generate(42, 16);
+//! assert_eq!(case.program.instructions(), Case::generate(42, 16).program.instructions());
+//!
+//! let outcome = fuzz::check(&case, &Options::default()).unwrap();
+//! assert!(outcome.steps <= Options::default().fuel);
+//! ```
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::Duration;
+
+use crate::codegen::Generator;
+use crate::error::WsError;
+use crate::interpreter::{EofBehavior, Interrupt, Streams, VM};
+use crate::lexer::Lexer;
+use crate::parser::{Instruction, Parser};
+use crate::program::Program;
+
+/// Labels a generated program picks from; few enough that most jumps and
+/// calls find their label.
+const LABELS: [&str; 4] = [" ", "\t", "  ", " \t"];
+
+/// Characters a generated input is made of, so that both ReadChar and
+/// ReadNumber see something they can read.
+const INPUT_CHARS: &[u8] = b"0123456789-\nab ";
+
+/// Limits for running a generated program.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Steps a program may take before it is stopped.
+    pub fuel: u64,
+    /// Time after which a program that is still running counts as hung.
+    pub timeout: Duration,
+    pub max_stack: usize,
+    pub max_heap_size: usize,
+    pub max_call_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            fuel: 10_000,
+            timeout: Duration::from_secs(1),
+            max_stack: 1024,
+            max_heap_size: 1024,
+            max_call_depth: 1024,
+        }
+    }
+}
+
+/// A generated program and the input it is run with.
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub seed: u64,
+    pub program: Program,
+    pub input: String,
+}
+
+impl Case {
+    /// The program of `len` random instructions, plus the marks of the labels
+    /// it uses, and the input for `seed`.
+    pub fn generate(seed: u64, len: usize) -> Self {
+        let mut rng = Rng(seed);
+
+        // instructions that would underflow the stack, were the program run
+        // straight through, become pushes so that runs get past the start
+        let mut instructions: Vec<Instruction> = Vec::with_capacity(len);
+        let mut depth = 0;
+        for _ in 0..len {
+            let mut instruction = rng.instruction();
+            if instruction.stack_arguments() > depth {
+                instruction = Instruction::Push(rng.number());
+            }
+
+            depth -= instruction.stack_arguments();
+            depth += results(&instruction);
+            instructions.push(instruction);
+        }
+        for label in LABELS {
+            let marked = instructions
+                .iter()
+                .any(|instruction| matches!(instruction, Instruction::MarkLocation(mark) if mark == label));
+            if !marked {
+                let at = rng.below(instructions.len() as u64 + 1) as usize;
+                instructions.insert(at, Instruction::MarkLocation(label.to_string()));
+            }
+        }
+
+        let input_len = rng.below(32);
+        let input = (0..input_len)
+            .map(|_| INPUT_CHARS[rng.below(INPUT_CHARS.len() as u64) as usize] as char)
+            .collect();
+
+        Self {
+            seed,
+            program: Program::new(instructions),
+            input,
+        }
+    }
+}
+
+/// How a generated program stopped; errors such as a stack underflow are what
+/// random programs usually run into, and aren't failures.
+#[derive(Debug)]
+pub struct Outcome {
+    pub error: Option<WsError>,
+    pub steps: u64,
+}
+
+/// Something the interpreter must never do, whatever the program.
+#[derive(Debug, thiserror::Error)]
+pub enum Failure {
+    #[error("panicked: {0}")]
+    Panic(String),
+    /// The program still ran when its time was up, although its fuel should
+    /// have stopped it long before.
+    #[error("hung for more than {0:?}")]
+    Hang(Duration),
+    /// The VM or the code generator broke one of its guarantees.
+    #[error("invariant violated: {0}")]
+    Invariant(String),
+}
+
+/// Runs `case` within `options`, checking that the program survives a trip
+/// through the code generator and that the VM keeps to its limits.
+pub fn check(case: &Case, options: &Options) -> Result<Outcome, Failure> {
+    let checked = panic::catch_unwind(AssertUnwindSafe(|| {
+        check_round_trips(&case.program)?;
+        run(case, options)
+    }));
+
+    checked.unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+
+        Err(Failure::Panic(message))
+    })
+}
+
+fn check_round_trips(program: &Program) -> Result<(), Failure> {
+    let source = Generator::new().generate(program);
+    let parsed: Program = Parser::new(Lexer::new(source).lex())
+        .parse()
+        .map_err(|error| Failure::Invariant(format!("generated source doesn't parse: {error}")))?;
+    if parsed.instructions() != program.instructions() {
+        return Err(Failure::Invariant(
+            "generated source parses to another program".to_string(),
+        ));
+    }
+
+    for instruction in program.instructions() {
+        let mnemonic = instruction.to_string();
+        if mnemonic.parse::<Instruction>().ok().as_ref() != Some(instruction) {
+            return Err(Failure::Invariant(format!(
+                "`{mnemonic}` doesn't parse back to {instruction:?}"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn run(case: &Case, options: &Options) -> Result<Outcome, Failure> {
+    let mut vm: VM = VM::builder()
+        .fuel(options.fuel)
+        .max_stack(options.max_stack)
+        .max_heap_size(options.max_heap_size)
+        .max_call_depth(options.max_call_depth)
+        .eof(EofBehavior::MinusOne)
+        .host_io(Streams::new(
+            io::Cursor::new(case.input.clone()),
+            io::sink(),
+        ))
+        .build();
+    vm.interrupt = Some(Interrupt::after(options.timeout));
+
+    let error = vm.execute(&case.program).err();
+    if let Some(WsError::Interrupted(_)) = error {
+        return Err(Failure::Hang(options.timeout));
+    }
+
+    let violation = if vm.steps > options.fuel {
+        Some(format!("took {} steps on {} fuel", vm.steps, options.fuel))
+    } else if vm.stack.len() > options.max_stack {
+        Some(format!("stack grew to {} items", vm.stack.len()))
+    } else if vm.call_stack.len() > options.max_call_depth {
+        Some(format!("calls nested {} deep", vm.call_stack.len()))
+    } else {
+        vm.heap
+            .high_water_mark()
+            .filter(|address| *address >= options.max_heap_size)
+            .map(|address| format!("heap written at {address}"))
+    };
+    if let Some(violation) = violation {
+        return Err(Failure::Invariant(violation));
+    }
+
+    Ok(Outcome {
+        error,
+        steps: vm.steps,
+    })
+}
+
+/// Number of values `instruction` leaves on the stack, after popping its
+/// [`Instruction::stack_arguments`].
+fn results(instruction: &Instruction) -> usize {
+    match instruction {
+        Instruction::Swap => 2,
+        Instruction::Push(_)
+        | Instruction::Duplicate
+        | Instruction::Copy(_)
+        | Instruction::Slide(_)
+        | Instruction::Add
+        | Instruction::Substract
+        | Instruction::Multiply
+        | Instruction::Divide
+        | Instruction::Modulo
+        | Instruction::HeapRetrieve => 1,
+        _ => 0,
+    }
+}
+
+/// SplitMix64, which is plenty for picking instructions and keeps the crate
+/// free of a random number dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `bound`, which must not be 0.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    /// Mostly small numbers, so that copies, slides and heap addresses often
+    /// hit, with the extremes now and then to provoke overflows.
+    fn number(&mut self) -> i32 {
+        match self.below(10) {
+            0 => i32::MIN,
+            1 => i32::MAX,
+            _ => self.below(17) as i32 - 8,
+        }
+    }
+
+    fn label(&mut self) -> String {
+        LABELS[self.below(LABELS.len() as u64) as usize].to_string()
+    }
+
+    fn instruction(&mut self) -> Instruction {
+        // pushes come up most, so that the other instructions find something
+        // on the stack
+        match self.below(28) {
+            0..=6 => Instruction::Push(self.number()),
+            7 => Instruction::Duplicate,
+            8 => Instruction::Copy(self.number()),
+            9 => Instruction::Swap,
+            10 => Instruction::Discard,
+            11 => Instruction::Slide(self.number()),
+            12 => Instruction::Add,
+            13 => Instruction::Substract,
+            14 => Instruction::Multiply,
+            15 => Instruction::Divide,
+            16 => Instruction::Modulo,
+            17 => Instruction::HeapStore,
+            18 => Instruction::HeapRetrieve,
+            19 => Instruction::MarkLocation(self.label()),
+            20 => Instruction::Call(self.label()),
+            21 => Instruction::Jump(self.label()),
+            22 => Instruction::JumpIfZero(self.label()),
+            23 => Instruction::JumpIfNegative(self.label()),
+            24 => Instruction::EndSubroutine,
+            25 => match self.below(2) {
+                0 => Instruction::OutputChar,
+                _ => Instruction::OutputNumber,
+            },
+            26 => match self.below(2) {
+                0 => Instruction::ReadChar,
+                _ => Instruction::ReadNumber,
+            },
+            _ => Instruction::EndProgram,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_case() {
+        let (first, second) = (Case::generate(7, 64), Case::generate(7, 64));
+
+        assert_eq!(first.program.instructions(), second.program.instructions());
+        assert_eq!(first.input, second.input);
+        assert_ne!(
+            first.program.instructions(),
+            Case::generate(8, 64).program.instructions()
+        );
+    }
+
+    #[test]
+    fn generated_programs_pass() {
+        let options = Options::default();
+
+        for seed in 0..500 {
+            let case = Case::generate(seed, 32);
+            if let Err(failure) = check(&case, &options) {
+                panic!("seed {seed}: {failure}");
+            }
+        }
+    }
+
+    #[test]
+    fn reports_hangs() {
+        let options = Options {
+            timeout: Duration::ZERO,
+            ..Options::default()
+        };
+
+        let checked = check(&Case::generate(1, 8), &options);
+        assert!(matches!(checked, Err(Failure::Hang(_))));
+    }
+}
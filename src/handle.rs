@@ -0,0 +1,204 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::cell::WhitespaceInt;
+use crate::error::{Result, WsError};
+use crate::interpreter::VM;
+use crate::parser::Instruction;
+
+/// Number of executed instructions between two [`Event::Progress`] events.
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+#[derive(Debug)]
+pub enum Event {
+    /// Text produced by OutputChar/OutputNumber since the previous event.
+    Output(String),
+    Progress {
+        steps: u64,
+    },
+    /// Sent once; the program ended, failed, or was killed.
+    Finished(Result<()>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum State {
+    Running,
+    Paused,
+    Killed,
+}
+
+#[derive(Debug)]
+struct Control {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Control {
+    fn transition(&self, from: &[State], to: State) {
+        let mut state = self.state.lock().unwrap();
+        if from.contains(&state) {
+            *state = to;
+            self.changed.notify_all();
+        }
+    }
+
+    /// Blocks while paused, returns `false` once killed.
+    fn wait_until_runnable(&self) -> bool {
+        let state = self
+            .changed
+            .wait_while(self.state.lock().unwrap(), |state| *state == State::Paused)
+            .unwrap();
+
+        *state != State::Killed
+    }
+}
+
+/// A program running on a background thread.
+///
+/// Output is captured and forwarded as [`Event::Output`], on top of being
+/// written by the VM's [`VM::host_io`]; give it one that discards output to
+/// only get the events.
+#[derive(Debug)]
+pub struct ExecutionHandle<T = i32> {
+    control: Arc<Control>,
+    events: Receiver<Event>,
+    thread: JoinHandle<VM<T>>,
+}
+
+impl<T: WhitespaceInt> ExecutionHandle<T> {
+    pub(crate) fn spawn(mut vm: VM<T>, program: Vec<Instruction<T>>) -> Self {
+        let control = Arc::new(Control {
+            state: Mutex::new(State::Running),
+            changed: Condvar::new(),
+        });
+        let (sender, events) = mpsc::channel();
+
+        let thread = {
+            let control = Arc::clone(&control);
+            thread::spawn(move || {
+                vm.capture_output();
+                vm.load_labels(&program);
+
+                let result = run(&mut vm, &program, &control, &sender);
+
+                forward_output(&mut vm, &sender);
+                let _ = sender.send(Event::Finished(result));
+
+                vm
+            })
+        };
+
+        Self {
+            control,
+            events,
+            thread,
+        }
+    }
+
+    pub fn events(&self) -> &Receiver<Event> {
+        &self.events
+    }
+
+    pub fn pause(&self) {
+        self.control.transition(&[State::Running], State::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.control.transition(&[State::Paused], State::Running);
+    }
+
+    pub fn kill(&self) {
+        self.control
+            .transition(&[State::Running, State::Paused], State::Killed);
+    }
+
+    /// Waits for the execution to end and returns the VM in its final state.
+    pub fn join(self) -> thread::Result<VM<T>> {
+        self.thread.join()
+    }
+}
+
+fn run<T: WhitespaceInt>(
+    vm: &mut VM<T>,
+    program: &[Instruction<T>],
+    control: &Control,
+    sender: &Sender<Event>,
+) -> Result<()> {
+    let mut steps: u64 = 0;
+
+    loop {
+        if !control.wait_until_runnable() {
+            return Err(WsError::Killed);
+        }
+
+        if !vm.advance(program)? {
+            return Ok(());
+        }
+
+        forward_output(vm, sender);
+
+        steps += 1;
+        if steps.is_multiple_of(PROGRESS_INTERVAL) {
+            let _ = sender.send(Event::Progress { steps });
+        }
+    }
+}
+
+fn forward_output<T: WhitespaceInt>(vm: &mut VM<T>, sender: &Sender<Event>) {
+    if let Some(output) = vm.captured_output_mut() {
+        if !output.is_empty() {
+            let _ = sender.send(Event::Output(std::mem::take(output)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Streams;
+
+    fn quiet_vm() -> VM {
+        VM::builder()
+            .host_io(Streams::new(std::io::empty(), std::io::sink()))
+            .build()
+    }
+
+    #[test]
+    fn output_then_finished() {
+        let program = vec![
+            Instruction::Push(42),
+            Instruction::OutputNumber,
+            Instruction::EndProgram,
+        ];
+
+        let handle = quiet_vm().spawn(program);
+        let events: Vec<Event> = handle.events().iter().collect();
+
+        assert!(matches!(&events[0], Event::Output(text) if text == "42"));
+        assert!(matches!(events[1], Event::Finished(Ok(()))));
+        assert!(handle.join().unwrap().stack.is_empty());
+    }
+
+    #[test]
+    fn kill_paused_loop() {
+        let program = vec![
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Jump(" ".to_string()),
+        ];
+
+        let handle = quiet_vm().spawn(program);
+        handle.pause();
+        handle.resume();
+        handle.pause();
+        handle.kill();
+
+        let finished = handle
+            .events()
+            .iter()
+            .find(|event| matches!(event, Event::Finished(_)));
+
+        assert!(matches!(finished, Some(Event::Finished(Err(_)))));
+        handle.join().unwrap();
+    }
+}
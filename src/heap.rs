@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::cell::WhitespaceInt;
+
+/// Program heap with unbounded addressing.
+///
+/// Low addresses live in a dense vector; anything past it is stored in a map
+/// so that programs can use arbitrarily large addresses. Unset cells read as 0.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Heap<T> {
+    dense: Vec<T>,
+    sparse: HashMap<usize, T>,
+    /// Addresses stored to so far, when tracking is enabled.
+    initialized: Option<HashSet<usize>>,
+    /// Highest address stored to so far.
+    #[cfg_attr(feature = "serde", serde(default))]
+    high_water_mark: Option<usize>,
+}
+
+impl<T: WhitespaceInt> Heap<T> {
+    pub fn new(dense_size: usize) -> Self {
+        Self {
+            dense: vec![T::zero(); dense_size],
+            sparse: HashMap::new(),
+            initialized: None,
+            high_water_mark: None,
+        }
+    }
+
+    /// Starts remembering which addresses have been stored to, so that
+    /// [`Heap::is_initialized`] can tell them apart from untouched cells.
+    pub fn track_initialized(&mut self) {
+        self.initialized.get_or_insert_with(HashSet::new);
+    }
+
+    /// Whether `address` was stored to; always true when not tracking.
+    pub fn is_initialized(&self, address: usize) -> bool {
+        self.initialized
+            .as_ref()
+            .is_none_or(|initialized| initialized.contains(&address))
+    }
+
+    pub fn get(&self, address: usize) -> T {
+        match self.dense.get(address) {
+            Some(value) => value.clone(),
+            None => self.sparse.get(&address).cloned().unwrap_or_else(T::zero),
+        }
+    }
+
+    /// Highest address stored to, if any.
+    pub fn high_water_mark(&self) -> Option<usize> {
+        self.high_water_mark
+    }
+
+    pub fn set(&mut self, address: usize, value: T) {
+        if let Some(initialized) = &mut self.initialized {
+            initialized.insert(address);
+        }
+        self.high_water_mark = self.high_water_mark.max(Some(address));
+
+        if address >= self.dense.len() && address < self.dense.len().max(1) * 2 {
+            self.grow(address + 1);
+        }
+
+        if let Some(cell) = self.dense.get_mut(address) {
+            *cell = value;
+        } else if value.is_zero() {
+            self.sparse.remove(&address);
+        } else {
+            self.sparse.insert(address, value);
+        }
+    }
+
+    /// Sets every cell back to 0, keeping the dense region's allocation.
+    pub fn clear(&mut self) {
+        self.dense.fill(T::zero());
+        self.sparse.clear();
+        self.high_water_mark = None;
+
+        if let Some(initialized) = &mut self.initialized {
+            initialized.clear();
+        }
+    }
+
+    /// Extends the dense region to at least `len` cells, doubling it so that
+    /// sequential stores don't reallocate every time.
+    fn grow(&mut self, len: usize) {
+        let old_len = self.dense.len();
+        let new_len = len.max(old_len * 2);
+        self.dense.resize(new_len, T::zero());
+
+        for address in old_len..new_len {
+            if let Some(value) = self.sparse.remove(&address) {
+                self.dense[address] = value;
+            }
+        }
+    }
+
+    /// Non-zero cells in address order.
+    pub fn cells(&self) -> Vec<(usize, &T)> {
+        let mut sparse: Vec<(usize, &T)> = self
+            .sparse
+            .iter()
+            .map(|(address, value)| (*address, value))
+            .collect();
+        sparse.sort_unstable_by_key(|(address, _)| *address);
+
+        self.dense
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| !value.is_zero())
+            .chain(sparse)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dense_and_sparse() {
+        let mut heap: Heap<i32> = Heap::new(4);
+        heap.set(2, 7);
+        heap.set(1 << 40, -1);
+        heap.set(10, 3);
+        heap.set(10, 0);
+
+        assert_eq!(heap.get(2), 7);
+        assert_eq!(heap.get(1 << 40), -1);
+        assert_eq!(heap.get(10), 0);
+        assert_eq!(heap.get(5), 0);
+        assert_eq!(heap.cells(), vec![(2, &7), (1 << 40, &-1)]);
+        assert_eq!(heap.high_water_mark(), Some(1 << 40));
+    }
+
+    #[test]
+    fn grows_on_sequential_stores() {
+        let mut heap: Heap<i32> = Heap::new(2);
+        heap.set(6, 6);
+        for address in 0..5 {
+            heap.set(address, 1);
+        }
+
+        assert_eq!(heap.dense.len(), 8);
+        assert!(heap.sparse.is_empty());
+        assert_eq!(heap.get(6), 6);
+    }
+
+    #[test]
+    fn initialized_tracking() {
+        let mut heap: Heap<i32> = Heap::new(4);
+        assert!(heap.is_initialized(1));
+
+        heap.track_initialized();
+        heap.set(1, 0);
+        assert!(heap.is_initialized(1));
+        assert!(!heap.is_initialized(2));
+
+        heap.clear();
+        assert!(!heap.is_initialized(1));
+    }
+}
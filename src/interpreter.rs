@@ -1,218 +1,2749 @@
+use std::any::Any;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, IsTerminal, LineWriter, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, bail, Context, Result};
-
+use crate::cell::{DivisionSemantics, OverflowPolicy, WhitespaceInt};
+use crate::error::{IoContext, Result, WsError};
+use crate::executor::Executor;
+use crate::handle::ExecutionHandle;
+use crate::heap::Heap;
+use crate::observer::Observer;
 use crate::parser::Instruction;
+use crate::profile::{HeapHeatmap, StackTimeline, Tracer};
+use crate::program::Program;
 
-#[derive(Debug)]
-pub struct VM {
-    instruction_ptr: usize,
-    pub stack: Vec<i32>,
-    labels: HashMap<String, usize>,
-    pub heap: Vec<i32>,
+/// Where the program's input comes from and its output goes to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum IoMode {
+    /// Perform I/O with [`VM::host_io`], the terminal unless set otherwise.
+    #[default]
+    Terminal,
+    /// Discard all output and read zeros, for benchmarking.
+    Null,
+    /// Perform no I/O and report it as [`IoEvent`]s from [`VM::next_event`]
+    /// instead; input is supplied with [`VM::feed_input`].
+    Events,
 }
 
-impl VM {
-    pub fn new() -> Self {
-        Self::with_heap_size(1024)
+/// How ReadChar gets its character.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum CharInput {
+    /// Use the console when stdin is a terminal, plain stdin otherwise.
+    #[default]
+    Auto,
+    /// Read a single keypress from the terminal, without waiting for Enter.
+    Console,
+    /// Read the next UTF-8 character from stdin.
+    Stdin,
+}
+
+/// What ReadChar and ReadNumber do once the input is exhausted.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum EofBehavior {
+    /// Stop with an [`EndOfInput`] error.
+    #[default]
+    Error,
+    /// Store -1.
+    MinusOne,
+    /// Store 0.
+    Zero,
+}
+
+/// The program read past the end of its input with [`EofBehavior::Error`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EndOfInput {
+    pub instruction: usize,
+}
+
+impl fmt::Display for EndOfInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "end of input at instruction {}", self.instruction)
     }
+}
 
-    pub fn with_heap_size(heap_size: usize) -> Self {
+impl std::error::Error for EndOfInput {}
+
+/// A reader of program input, e.g. a file given with `--input`.
+pub struct Input(Box<dyn BufRead + Send>);
+
+impl Input {
+    pub fn new(reader: impl Read + Send + 'static) -> Self {
+        Self(Box::new(BufReader::new(reader)))
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl BufRead for Input {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.0.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.0.consume(amount)
+    }
+}
+
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Input")
+    }
+}
+
+/// A writer of program output, buffered line by line; flush it once the
+/// program is done.
+pub struct Output(Box<dyn Write + Send>);
+
+impl Output {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Box::new(LineWriter::new(writer)))
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Output")
+    }
+}
+
+/// Performs the program's input and output: [`Terminal`] by default, or
+/// [`Streams`], [`FedInput`], [`Capture`] or one of the host's own, such as
+/// a test script, GUI or WASM bridge.
+pub trait WsIo: Any + Send {
+    /// The next character of input, or `None` at the end of it.
+    fn read_char(&mut self) -> io::Result<Option<char>>;
+
+    /// Appends the next line of input to `line`, returning the number of
+    /// bytes read; 0 means the input has ended.
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize>;
+
+    fn write_str(&mut self, text: &str) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for dyn WsIo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WsIo")
+    }
+}
+
+/// [`WsIo`] over the terminal: input from stdin, or keypresses from the
+/// console as [`CharInput`] says, and output to stdout unless it is
+/// redirected to another writer.
+#[derive(Debug)]
+pub struct Terminal<W = io::Stdout> {
+    pub char_input: CharInput,
+    /// Shown on stderr before reading input, for interactive use.
+    pub prompts: Option<Prompts>,
+    pub writer: W,
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
         Self {
-            instruction_ptr: 0,
-            stack: Vec::new(),
-            labels: HashMap::new(),
-            heap: vec![0; heap_size],
+            char_input: CharInput::default(),
+            prompts: None,
+            writer: io::stdout(),
         }
     }
+}
 
-    pub fn execute(&mut self, instructions: &[Instruction]) -> Result<()> {
-        for (i, instr) in instructions.iter().enumerate() {
-            if let Instruction::MarkLocation(label) = instr {
-                self.labels.insert(label.clone(), i);
-            }
+impl<W: Write + Send + 'static> Terminal<W> {
+    /// Redirects program input from `input` and output to `output` where
+    /// given, keeping the terminal for the rest.
+    pub fn redirect(self, input: Option<Input>, output: Option<Output>) -> Box<dyn WsIo> {
+        match (input, output) {
+            (None, None) => Box::new(self),
+            (None, Some(output)) => Box::new(Terminal {
+                char_input: self.char_input,
+                prompts: self.prompts,
+                writer: output,
+            }),
+            (Some(input), None) => Box::new(Streams {
+                reader: input,
+                writer: self.writer,
+            }),
+            (Some(input), Some(output)) => Box::new(Streams {
+                reader: input,
+                writer: output,
+            }),
         }
+    }
 
-        loop {
-            let stack_len = self.stack.len();
+    fn show_prompt(&mut self, prompt: impl Fn(&Prompts) -> &str) {
+        if let Some(prompts) = &self.prompts {
+            // make sure the program's own output appears before the prompt
+            let _ = self.writer.flush();
+            eprint!("{}", prompt(prompts));
+        }
+    }
+}
 
-            let instruction = instructions
-                .get(self.instruction_ptr)
-                .ok_or_else(|| anyhow!("no more instructions"))?;
+impl<W: Write + Send + 'static> WsIo for Terminal<W> {
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        self.show_prompt(|prompts| &prompts.char);
 
-            match instruction {
-                Instruction::Push(number) => {
-                    self.stack.push(*number);
-                }
-                Instruction::Duplicate => {
-                    let element = self.peek_stack()?;
+        let console = match self.char_input {
+            CharInput::Auto => io::stdin().is_terminal(),
+            CharInput::Console => true,
+            CharInput::Stdin => false,
+        };
+        if !console {
+            return read_char(&mut io::stdin().lock());
+        }
 
-                    self.stack.push(*element);
-                }
-                Instruction::Copy(_) => unimplemented!("copy"),
-                Instruction::Swap => {
-                    self.stack.swap(stack_len - 1, stack_len - 2);
-                }
-                Instruction::Discard => {
-                    self.pop_stack()?;
-                }
-                Instruction::Slide(_) => unimplemented!("slide"),
-                Instruction::Add => {
-                    let left = self.pop_stack()?;
-                    let right = self.pop_stack()?;
+        match console::Term::stdout().read_char() {
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            chr => chr.map(Some),
+        }
+    }
 
-                    self.stack.push(left + right);
-                }
-                Instruction::Substract => {
-                    let left = self.pop_stack()?;
-                    let right = self.pop_stack()?;
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        self.show_prompt(|prompts| &prompts.number);
 
-                    self.stack.push(left - right);
-                }
-                Instruction::Multiply => {
-                    let left = self.pop_stack()?;
-                    let right = self.pop_stack()?;
+        io::stdin().read_line(line)
+    }
 
-                    self.stack.push(left * right);
-                }
-                Instruction::Divide => {
-                    let left = self.pop_stack()?;
-                    let right = self.pop_stack()?;
-
-                    self.stack.push(
-                        left.checked_div(right)
-                            .ok_or_else(|| anyhow!("trying to divide {left} by zero"))?,
-                    );
-                }
-                Instruction::Modulo => {
-                    let left = self.pop_stack()?;
-                    let right = self.pop_stack()?;
-                    self.stack.push(
-                        left.checked_rem(right).ok_or_else(|| {
-                            anyhow!("trying to compute remainder of {left} by zero")
-                        })?,
-                    );
-                }
-                Instruction::HeapStore => {
-                    let value = self.pop_stack()?;
-                    let address = self.pop_stack()?;
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.writer.write_all(text.as_bytes())
+    }
 
-                    self.store_heap(address, value)?;
-                }
-                Instruction::HeapRetrieve => {
-                    let address = self.pop_stack()?;
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
 
-                    let value = self.get_heap(address)?;
+/// [`WsIo`] over any reader and writer.
+#[derive(Debug)]
+pub struct Streams<R, W> {
+    pub reader: R,
+    pub writer: W,
+}
 
-                    self.stack.push(value);
-                }
-                Instruction::MarkLocation(_) => {}
-                Instruction::Call(label) => {
-                    self.stack.push(i32::try_from(self.instruction_ptr)? + 1);
-                    self.jump(label)?;
-                }
-                Instruction::Jump(label) => {
-                    self.jump(label)?;
-                }
-                Instruction::JumpIfZero(label) => {
-                    let top = self.peek_stack()?;
+impl<R: Read, W: Write> Streams<R, W> {
+    pub fn new(reader: R, writer: W) -> Streams<BufReader<R>, W> {
+        Streams {
+            reader: BufReader::new(reader),
+            writer,
+        }
+    }
+}
 
-                    if *top == 0 {
-                        self.jump(label)?;
-                    }
-                }
-                Instruction::JumpIfNegative(label) => {
-                    let top = self.peek_stack()?;
+impl<R: BufRead + Send + 'static, W: Write + Send + 'static> WsIo for Streams<R, W> {
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        read_char(&mut self.reader)
+    }
 
-                    if *top < 0 {
-                        self.jump(label)?;
-                    }
-                }
-                Instruction::EndSubroutine => {
-                    let addr = self.pop_stack()?;
-                    self.instruction_ptr = usize::try_from(addr).with_context(|| "invalid addr")?;
-                }
-                Instruction::EndProgram => break Ok(()),
-                Instruction::OutputChar => {
-                    let element = self.pop_stack()?;
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        self.reader.read_line(line)
+    }
 
-                    let chr = char::from_u32(
-                        u32::try_from(element).with_context(|| "invalid character in stack")?,
-                    )
-                    .ok_or_else(|| anyhow!("invalid character"))?;
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.writer.write_all(text.as_bytes())
+    }
 
-                    print!("{chr}");
-                }
-                Instruction::OutputNumber => {
-                    let element = self.pop_stack()?;
-                    print!("{element}");
-                }
-                Instruction::ReadChar => {
-                    let chr = console::Term::stdout()
-                        .read_char()
-                        .with_context(|| "reading a character")?;
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
 
-                    self.stack.push(chr as i32);
-                }
-                Instruction::ReadNumber => {
-                    let mut line = String::new();
-
-                    std::io::stdin()
-                        .read_line(&mut line)
-                        .with_context(|| "reading line")?;
-
-                    self.stack.push(
-                        line.trim()
-                            .parse()
-                            .with_context(|| "parsing line to number")?,
-                    );
-                }
-            };
+impl Streams<BufReader<ChannelReader>, ChannelWriter> {
+    /// Program input received from `input` and output sent to `output` a
+    /// byte at a time, so a GUI or bot can talk to the program from another
+    /// thread like a coprocess.
+    pub fn channels(input: Receiver<u8>, output: Sender<u8>) -> Self {
+        Streams::new(ChannelReader(input), ChannelWriter(output))
+    }
+}
+
+/// Reads the bytes sent over a channel; the input ends once every sender is
+/// dropped.
+#[derive(Debug)]
+pub struct ChannelReader(pub Receiver<u8>);
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some((first, rest)) = buf.split_first_mut() else {
+            return Ok(0);
+        };
+        // wait for one byte, then take whatever else is already there
+        let Ok(byte) = self.0.recv() else {
+            return Ok(0);
+        };
+        *first = byte;
 
-            self.instruction_ptr += 1;
+        let mut len = 1;
+        for slot in rest {
+            match self.0.try_recv() {
+                Ok(byte) => *slot = byte,
+                Err(_) => break,
+            }
+            len += 1;
         }
+
+        Ok(len)
     }
+}
+
+/// Sends the bytes written over a channel; writing fails with
+/// [`io::ErrorKind::BrokenPipe`] once the receiver is dropped.
+#[derive(Debug)]
+pub struct ChannelWriter(pub Sender<u8>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for byte in buf {
+            self.0
+                .send(*byte)
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        }
 
-    fn pop_stack(&mut self) -> Result<i32> {
-        self.stack
-            .pop()
-            .ok_or_else(|| anyhow!("empty stack during pop"))
+        Ok(buf.len())
     }
 
-    fn peek_stack(&self) -> Result<&i32> {
-        self.stack
-            .last()
-            .ok_or_else(|| anyhow!("empty stack during peek"))
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
     }
+}
 
-    fn jump(&mut self, label: &String) -> Result<()> {
-        self.instruction_ptr = *self
-            .labels
-            .get(label)
-            .ok_or_else(|| anyhow!("label not found"))?;
+/// Input handed to the VM a piece at a time with [`VM::feed_input`], which
+/// makes it the VM's [`WsIo`].
+///
+/// Reads that would go past what has been fed so far make [`VM::step`]
+/// return [`StepOutcome::NeedsInput`] instead of blocking, until
+/// [`VM::close_input`] marks the end of the input. Output is collected, to be
+/// taken with [`FedInput::take_output`].
+#[derive(Debug, Clone, Default)]
+pub struct FedInput {
+    buffer: String,
+    closed: bool,
+    output: String,
+}
+
+impl FedInput {
+    /// Whether `instruction` can run without waiting for more input.
+    fn ready<T>(&self, instruction: &Instruction<T>) -> bool {
+        self.closed
+            || match instruction {
+                Instruction::ReadChar => !self.buffer.is_empty(),
+                Instruction::ReadNumber => self.buffer.contains('\n'),
+                _ => true,
+            }
+    }
+
+    /// What the program printed since the last call.
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl WsIo for FedInput {
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        let chr = self.buffer.chars().next();
+        if let Some(chr) = chr {
+            self.buffer.drain(..chr.len_utf8());
+        }
+
+        Ok(chr)
+    }
 
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        let len = self
+            .buffer
+            .find('\n')
+            .map_or(self.buffer.len(), |newline| newline + 1);
+        line.extend(self.buffer.drain(..len));
+
+        Ok(len)
+    }
+
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.output.push_str(text);
         Ok(())
     }
+}
+
+/// [`WsIo`] that performs I/O with `inner` and keeps a copy of the output,
+/// see [`VM::capture_output`].
+#[derive(Debug)]
+pub struct Capture {
+    pub inner: Box<dyn WsIo>,
+    pub output: String,
+}
+
+impl Capture {
+    pub fn new(inner: impl WsIo) -> Self {
+        Self {
+            inner: Box::new(inner),
+            output: String::new(),
+        }
+    }
+}
+
+impl WsIo for Capture {
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        self.inner.read_char()
+    }
+
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        self.inner.read_line(line)
+    }
+
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.output.push_str(text);
+        self.inner.write_str(text)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Something the program did that the host has to act on, returned by
+/// [`VM::next_event`] in [`IoMode::Events`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum IoEvent<T = i32> {
+    /// The program printed a character.
+    Output(char),
+    /// The program printed a number.
+    OutputNumber(T),
+    /// The next instruction is ReadChar and no input is left.
+    NeedInputChar,
+    /// The next instruction is ReadNumber and no full line of input is left.
+    NeedInputNumber,
+    /// The program has ended.
+    Halted,
+}
+
+/// What happened when [`VM::step`] was called.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StepOutcome {
+    /// An instruction ran and the program goes on.
+    Continue,
+    /// The program has ended.
+    Halted,
+    /// The next instruction reads input that hasn't been fed yet; nothing
+    /// ran. See [`VM::feed_input`].
+    NeedsInput,
+}
+
+/// The program stopped for input while being run to completion.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct InputPending {
+    pub instruction: usize,
+}
+
+impl fmt::Display for InputPending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "instruction {} needs more input than was fed",
+            self.instruction
+        )
+    }
+}
+
+impl std::error::Error for InputPending {}
+
+/// What a program produced when run by [`VM::execute_capture`].
+#[derive(Debug)]
+pub struct RunOutput<T = i32> {
+    /// Everything the program printed, up to the error if it failed.
+    pub output: Vec<u8>,
+    /// Why the program stopped early, if it did.
+    pub error: Option<WsError>,
+    /// The exit status: what EndProgram popped with
+    /// [`VM::exit_code_from_stack`], 0 otherwise, or `None` if the program
+    /// failed.
+    pub exit_code: Option<T>,
+    pub stack: Vec<T>,
+    pub heap: Heap<T>,
+}
+
+impl<T> RunOutput<T> {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Prompts {
+    pub number: String,
+    pub char: String,
+}
+
+impl Default for Prompts {
+    fn default() -> Self {
+        Self {
+            number: "number> ".to_string(),
+            char: "char> ".to_string(),
+        }
+    }
+}
+
+/// The data stack grew past [`VM::max_stack`].
+///
+/// Returned as [`WsError::StackOverflow`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StackOverflow {
+    pub limit: usize,
+    pub instruction: usize,
+}
+
+impl fmt::Display for StackOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stack overflow: more than {} items at instruction {}",
+            self.limit, self.instruction
+        )
+    }
+}
+
+impl std::error::Error for StackOverflow {}
+
+/// [`VM::execute_with_fuel`] ran out of steps before the program ended, or
+/// the VM ran more instructions than its [`VM::fuel`] allows.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FuelExhausted {
+    pub steps: u64,
+}
+
+impl fmt::Display for FuelExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fuel exhausted after {} steps", self.steps)
+    }
+}
+
+impl std::error::Error for FuelExhausted {}
+
+/// A flag that stops a running VM at its next step, e.g. from another thread,
+/// optionally raised by itself at a deadline.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    flag: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl Interrupt {
+    /// Returns an interrupt that triggers by itself once `duration` elapsed.
+    ///
+    /// The deadline is checked by [`Interrupt::is_triggered`], so no thread
+    /// is left waiting for it.
+    pub fn after(duration: Duration) -> Self {
+        Self {
+            flag: Arc::default(),
+            deadline: Instant::now().checked_add(duration),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// The VM stopped because its [`Interrupt`] was triggered.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Interrupted {
+    pub instruction: usize,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted at instruction {}", self.instruction)
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// How [`VM::preload_args_with`] lays out command-line arguments.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ArgsConvention {
+    /// NUL-terminated strings in the heap from `address`, one after the
+    /// other. The stack holds the address of every argument, the first one
+    /// nearest the top, and the argument count on top of them.
+    Argv { address: usize },
+    /// The argument count at `address`, followed in the heap by the arguments
+    /// as NUL-terminated strings. The stack is left alone.
+    Heap { address: usize },
+    /// Every argument pushed as a NUL-terminated string with its first
+    /// character on top, the first argument nearest the top, and the
+    /// argument count on top of them. The heap is left alone.
+    Stack,
+}
+
+impl Default for ArgsConvention {
+    fn default() -> Self {
+        Self::Argv { address: 0 }
+    }
+}
+
+/// Heap cells allocated up front; the heap grows past this as needed.
+pub const DEFAULT_HEAP_SIZE: usize = 1024;
+
+/// A VM is `Send` whatever it is configured with, as every I/O handle it
+/// holds must be, so it can be moved to a worker thread to run. The terminal
+/// is only opened for the duration of a read.
+///
+/// With the `serde` feature, only the execution state (instruction pointer,
+/// stacks, labels and heap) is serialized; configuration is left at its
+/// defaults when deserializing.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VM<T = i32> {
+    instruction_ptr: usize,
+    pub stack: Vec<T>,
+    /// Pushing past this many items is a [`StackOverflow`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub max_stack: Option<usize>,
+    /// Index of each pending Call instruction.
+    pub call_stack: Vec<usize>,
+    /// Calls nested deeper than this are an error.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub max_call_depth: Option<usize>,
+    labels: HashMap<String, usize>,
+    pub heap: Heap<T>,
+    /// Addresses at or past this limit are a heap overflow.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub max_heap_size: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub io: IoMode,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub eof: EofBehavior,
+    /// Treat running past the last instruction like EndProgram.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub implicit_exit: bool,
+    /// Make EndProgram pop the top of the stack into `exit_code`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub exit_code_from_stack: bool,
+    /// Set by EndProgram when `exit_code_from_stack` is enabled; 0 if the
+    /// stack was empty.
+    pub exit_code: Option<T>,
+    /// Number of instructions run since the VM was created or reset.
+    pub steps: u64,
+    /// Running more than this many instructions in total is a
+    /// [`FuelExhausted`] error.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub fuel: Option<u64>,
+    /// Performs all program I/O; the [`Terminal`] unless set otherwise.
+    #[cfg_attr(feature = "serde", serde(skip, default = "terminal"))]
+    pub host_io: Box<dyn WsIo>,
+    /// Output of the last step, waiting to be returned by
+    /// [`VM::next_event`].
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
+    pending_event: Option<IoEvent<T>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub overflow: OverflowPolicy,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub division: DivisionSemantics,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub heap_heatmap: Option<HeapHeatmap>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub stack_timeline: Option<StackTimeline>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub tracer: Option<Tracer>,
+    /// Notified as each instruction runs; see [`VM::observe`].
+    #[cfg_attr(feature = "serde", serde(skip, default = "Vec::new"))]
+    pub observers: Vec<Box<dyn Observer<T>>>,
+    /// Checked before every instruction.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub interrupt: Option<Interrupt>,
+}
 
-    fn get_heap(&self, address: i32) -> Result<i32> {
-        let address = usize::try_from(address).with_context(|| "invalid address")?;
+// keeps a field that isn't Send from sneaking into the VM
+const _: fn() = || {
+    fn assert_send<S: Send>() {}
+    assert_send::<VM>();
+};
 
-        if address >= self.heap.len() {
-            bail!("heap overflow");
+/// Clones the execution state and configuration.
+///
+/// What can't be cloned is left at its default in the copy: `host_io`, which
+/// is the [`Terminal`] again, `tracer` and `observers`. The copy shares the
+/// original's [`Interrupt`], if any.
+impl<T: Clone> Clone for VM<T> {
+    fn clone(&self) -> Self {
+        Self {
+            instruction_ptr: self.instruction_ptr,
+            stack: self.stack.clone(),
+            max_stack: self.max_stack,
+            call_stack: self.call_stack.clone(),
+            max_call_depth: self.max_call_depth,
+            labels: self.labels.clone(),
+            heap: self.heap.clone(),
+            max_heap_size: self.max_heap_size,
+            io: self.io,
+            eof: self.eof,
+            implicit_exit: self.implicit_exit,
+            exit_code_from_stack: self.exit_code_from_stack,
+            exit_code: self.exit_code.clone(),
+            steps: self.steps,
+            fuel: self.fuel,
+            host_io: terminal(),
+            pending_event: self.pending_event.clone(),
+            overflow: self.overflow,
+            division: self.division,
+            heap_heatmap: self.heap_heatmap.clone(),
+            stack_timeline: self.stack_timeline.clone(),
+            tracer: None,
+            observers: Vec::new(),
+            interrupt: self.interrupt.clone(),
         }
+    }
+}
+
+/// The execution state of a [`VM`], taken by [`VM::snapshot`] to roll back
+/// to with [`VM::restore`].
+#[derive(Debug, Clone)]
+pub struct Snapshot<T = i32> {
+    instruction_ptr: usize,
+    stack: Vec<T>,
+    call_stack: Vec<usize>,
+    heap: Heap<T>,
+    exit_code: Option<T>,
+    steps: u64,
+    pending_event: Option<IoEvent<T>>,
+}
+
+impl<T> Snapshot<T> {
+    pub fn instruction_ptr(&self) -> usize {
+        self.instruction_ptr
+    }
 
-        Ok(self.heap[address])
+    pub fn stack(&self) -> &[T] {
+        &self.stack
+    }
+}
+
+impl<T: WhitespaceInt> VM<T> {
+    /// Configures a VM step by step, for when the defaults of [`VM::new`]
+    /// don't fit.
+    pub fn builder() -> VMBuilder<T> {
+        VMBuilder {
+            vm: VM::new(),
+            input: None,
+            output: None,
+        }
     }
 
-    fn store_heap(&mut self, address: i32, value: i32) -> Result<()> {
-        let address = usize::try_from(address).with_context(|| "invalid address")?;
+    pub fn new() -> Self {
+        Self::with_heap_size(DEFAULT_HEAP_SIZE)
+    }
 
-        if address >= self.heap.len() {
-            bail!("heap overflow");
+    pub fn with_heap_size(heap_size: usize) -> Self {
+        Self {
+            instruction_ptr: 0,
+            stack: Vec::new(),
+            max_stack: None,
+            call_stack: Vec::new(),
+            max_call_depth: None,
+            labels: HashMap::new(),
+            heap: Heap::new(heap_size),
+            max_heap_size: None,
+            io: IoMode::default(),
+            eof: EofBehavior::default(),
+            implicit_exit: false,
+            exit_code_from_stack: false,
+            exit_code: None,
+            steps: 0,
+            fuel: None,
+            host_io: terminal(),
+            pending_event: None,
+            overflow: OverflowPolicy::default(),
+            division: DivisionSemantics::default(),
+            heap_heatmap: None,
+            stack_timeline: None,
+            tracer: None,
+            observers: Vec::new(),
+            interrupt: None,
         }
+    }
 
-        self.heap[address] = value;
+    /// Runs `instructions` from the current instruction pointer until the
+    /// program ends.
+    ///
+    /// The VM keeps its state afterwards; call [`VM::reset`] before running
+    /// another program, or the same one again.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn execute(&mut self, instructions: &[Instruction<T>]) -> Result<()> {
+        self.load_labels(instructions);
+
+        while self.advance(instructions)? {}
 
         Ok(())
     }
+
+    /// Like [`VM::execute`], but reads program input from `input` and returns
+    /// what the program printed along with its final state, instead of using
+    /// stdin and stdout. A failing program is reported in
+    /// [`RunOutput::error`], with its output and state up to the failure.
+    ///
+    /// The VM's own [`VM::host_io`] is put back afterwards.
+    pub fn execute_capture(
+        &mut self,
+        instructions: &[Instruction<T>],
+        input: &str,
+    ) -> RunOutput<T> {
+        let streams = Streams::new(io::Cursor::new(input.to_string()), Vec::new());
+        let host_io = std::mem::replace(&mut self.host_io, Box::new(streams));
+
+        let result = self.execute(instructions);
+
+        let streams = std::mem::replace(&mut self.host_io, host_io) as Box<dyn Any>;
+        let output = streams
+            .downcast::<Streams<BufReader<io::Cursor<String>>, Vec<u8>>>()
+            .map(|streams| streams.writer)
+            .unwrap_or_default();
+
+        let exit_code = match &result {
+            Ok(()) => Some(self.exit_code.clone().unwrap_or_else(T::zero)),
+            Err(_) => None,
+        };
+
+        RunOutput {
+            output,
+            error: result.err(),
+            exit_code,
+            stack: self.stack.clone(),
+            heap: self.heap.clone(),
+        }
+    }
+
+    /// Clears the stacks, heap, instruction pointer and captured output so the
+    /// VM can run another program.
+    ///
+    /// Configuration such as io mode, limits and profilers is kept, and the
+    /// heap keeps its allocation.
+    pub fn reset(&mut self) {
+        self.instruction_ptr = 0;
+        self.stack.clear();
+        self.call_stack.clear();
+        self.labels.clear();
+        self.heap.clear();
+        self.exit_code = None;
+        self.steps = 0;
+
+        if let Some(output) = self.captured_output_mut() {
+            output.clear();
+        }
+    }
+
+    /// Copies the execution state (instruction pointer, stacks, heap and step
+    /// count) so that [`VM::restore`] can roll back to it, e.g. after
+    /// exploring a branch.
+    ///
+    /// Input already read and output already written are not rolled back.
+    pub fn snapshot(&self) -> Snapshot<T> {
+        Snapshot {
+            instruction_ptr: self.instruction_ptr,
+            stack: self.stack.clone(),
+            call_stack: self.call_stack.clone(),
+            heap: self.heap.clone(),
+            exit_code: self.exit_code.clone(),
+            steps: self.steps,
+            pending_event: self.pending_event.clone(),
+        }
+    }
+
+    /// Puts the VM back in the state [`VM::snapshot`] took, keeping its
+    /// configuration and I/O.
+    pub fn restore(&mut self, snapshot: Snapshot<T>) {
+        self.instruction_ptr = snapshot.instruction_ptr;
+        self.stack = snapshot.stack;
+        self.call_stack = snapshot.call_stack;
+        self.heap = snapshot.heap;
+        self.exit_code = snapshot.exit_code;
+        self.steps = snapshot.steps;
+        self.pending_event = snapshot.pending_event;
+    }
+
+    /// Like [`VM::execute`], but runs at most `max_steps` instructions.
+    ///
+    /// If the program hasn't ended by then, a [`FuelExhausted`] error is
+    /// returned and the VM is left as is, so calling this again with the same
+    /// instructions picks up where it stopped.
+    #[tracing::instrument(level = "debug", name = "execute", skip(self, instructions))]
+    pub fn execute_with_fuel(
+        &mut self,
+        instructions: &[Instruction<T>],
+        max_steps: u64,
+    ) -> Result<()> {
+        self.load_labels(instructions);
+
+        for _ in 0..max_steps {
+            if !self.advance(instructions)? {
+                return Ok(());
+            }
+        }
+
+        Err(FuelExhausted { steps: max_steps }.into())
+    }
+
+    /// Makes command-line arguments available to the program, using the
+    /// default [`ArgsConvention::Argv`] layout from address 0.
+    pub fn preload_args(&mut self, args: &[String]) -> Result<()> {
+        self.preload_args_with(args, ArgsConvention::default())
+    }
+
+    /// Makes command-line arguments available to the program in the given
+    /// layout.
+    pub fn preload_args_with(&mut self, args: &[String], convention: ArgsConvention) -> Result<()> {
+        let argc = cell_from_usize(args.len())?;
+
+        match convention {
+            ArgsConvention::Argv { address } => {
+                let addresses = self.write_heap_strings(address, args)?;
+
+                self.stack.extend(addresses.into_iter().rev());
+                self.stack.push(argc);
+            }
+            ArgsConvention::Heap { address } => {
+                let strings = address.checked_add(1).ok_or(WsError::AddressOverflow)?;
+
+                self.write_heap_values(address, vec![argc])?;
+                self.write_heap_strings(strings, args)?;
+            }
+            ArgsConvention::Stack => {
+                for arg in args.iter().rev() {
+                    self.stack.push(T::zero());
+                    self.stack
+                        .extend(arg.chars().rev().map(|chr| T::from_char(chr)));
+                }
+
+                self.stack.push(argc);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stores NUL-terminated strings one after the other from `address`,
+    /// returning where each one starts.
+    fn write_heap_strings(&mut self, mut address: usize, strings: &[String]) -> Result<Vec<T>> {
+        let mut addresses = Vec::with_capacity(strings.len());
+
+        for string in strings {
+            addresses.push(cell_from_usize(address)?);
+            address = self.write_heap_string(address, string)?;
+        }
+
+        Ok(addresses)
+    }
+
+    /// Stores `values` at consecutive addresses starting at `address`, e.g. to
+    /// hand the program a data table, returning the address past the last one.
+    pub fn preload_heap(
+        &mut self,
+        address: usize,
+        values: impl IntoIterator<Item = T>,
+    ) -> Result<usize> {
+        self.write_heap_values(address, values.into_iter().collect())
+    }
+
+    /// Reads a NUL-terminated string starting at `address`.
+    pub fn read_heap_string(&self, mut address: usize) -> Result<String> {
+        let mut string = String::new();
+
+        loop {
+            match self.heap_char(address)? {
+                '\0' => return Ok(string),
+                chr => string.push(chr),
+            }
+
+            address = address.checked_add(1).ok_or(WsError::AddressOverflow)?;
+        }
+    }
+
+    /// Writes `string` NUL-terminated at `address`, returning the address
+    /// just past the terminator.
+    pub fn write_heap_string(&mut self, address: usize, string: &str) -> Result<usize> {
+        let values: Vec<T> = string
+            .chars()
+            .chain(['\0'])
+            .map(|chr| T::from_char(chr))
+            .collect();
+
+        self.write_heap_values(address, values)
+    }
+
+    /// Reads a string stored as its length followed by its characters.
+    pub fn read_prefixed_heap_string(&self, address: usize) -> Result<String> {
+        let len = self.heap.get(address);
+        let len = len
+            .to_usize()
+            .ok_or_else(|| WsError::InvalidStringLength(len.to_string()))?;
+
+        let end = address
+            .checked_add(1)
+            .and_then(|start| start.checked_add(len))
+            .ok_or(WsError::AddressOverflow)?;
+        self.check_heap_address(end - 1)?;
+
+        (address + 1..end)
+            .map(|address| self.heap_char(address))
+            .collect()
+    }
+
+    /// Writes `string` as its length followed by its characters, returning
+    /// the address just past the last character.
+    pub fn write_prefixed_heap_string(&mut self, address: usize, string: &str) -> Result<usize> {
+        let mut values = vec![cell_from_usize(string.chars().count())?];
+        values.extend(string.chars().map(|chr| T::from_char(chr)));
+
+        self.write_heap_values(address, values)
+    }
+
+    fn heap_char(&self, address: usize) -> Result<char> {
+        let value = self.heap.get(address);
+
+        value.to_char().ok_or_else(|| WsError::InvalidHeapChar {
+            value: value.to_string(),
+            address,
+        })
+    }
+
+    fn write_heap_values(&mut self, address: usize, values: Vec<T>) -> Result<usize> {
+        let end = address
+            .checked_add(values.len())
+            .ok_or(WsError::AddressOverflow)?;
+        if end > 0 {
+            self.check_heap_address(end - 1)?;
+        }
+
+        for (address, value) in (address..end).zip(values) {
+            self.heap.set(address, value);
+        }
+
+        Ok(end)
+    }
+
+    /// Runs `program` on a background thread.
+    pub fn spawn(self, program: Vec<Instruction<T>>) -> ExecutionHandle<T> {
+        ExecutionHandle::spawn(self, program)
+    }
+
+    /// Records where each label is marked; a label marked more than once
+    /// jumps to its first mark, as in [`Program::labels`].
+    pub fn load_labels(&mut self, instructions: &[Instruction<T>]) {
+        self.labels.clear();
+
+        for (i, instr) in instructions.iter().enumerate() {
+            if let Instruction::MarkLocation(label) = instr {
+                self.labels.entry(label.clone()).or_insert(i);
+            }
+        }
+    }
+
+    /// Appends `text` to the program input, switching [`VM::host_io`] over to
+    /// a [`FedInput`] if it wasn't one already.
+    pub fn feed_input(&mut self, text: &str) {
+        self.fed_input_or_default().buffer.push_str(text);
+    }
+
+    /// Marks the end of the fed input, so reads past it follow
+    /// [`VM::eof`] instead of waiting.
+    pub fn close_input(&mut self) {
+        self.fed_input_or_default().closed = true;
+    }
+
+    /// The fed input, if [`VM::host_io`] is one.
+    pub fn fed_input(&mut self) -> Option<&mut FedInput> {
+        (self.host_io.as_mut() as &mut dyn Any).downcast_mut()
+    }
+
+    fn fed_input_or_default(&mut self) -> &mut FedInput {
+        if self.fed_input().is_none() {
+            self.host_io = Box::new(FedInput::default());
+        }
+
+        self.fed_input().expect("host_io was just set to fed input")
+    }
+
+    /// Keeps a copy of the program output from now on, readable with
+    /// [`VM::captured_output`], by wrapping [`VM::host_io`] in a [`Capture`].
+    pub fn capture_output(&mut self) {
+        if self.captured_output().is_none() {
+            let host_io = std::mem::replace(&mut self.host_io, terminal());
+            self.host_io = Box::new(Capture {
+                inner: host_io,
+                output: String::new(),
+            });
+        }
+    }
+
+    /// What the program printed since [`VM::capture_output`], if it was
+    /// called.
+    pub fn captured_output(&self) -> Option<&str> {
+        let capture: Option<&Capture> = (self.host_io.as_ref() as &dyn Any).downcast_ref();
+        capture.map(|capture| capture.output.as_str())
+    }
+
+    pub fn captured_output_mut(&mut self) -> Option<&mut String> {
+        let capture: Option<&mut Capture> = (self.host_io.as_mut() as &mut dyn Any).downcast_mut();
+        capture.map(|capture| &mut capture.output)
+    }
+
+    /// Registers `observer` to be called back as instructions run.
+    pub fn observe(&mut self, observer: impl Observer<T> + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Runs `instructions` until the program does I/O or ends, for hosts
+    /// driving the VM in [`IoMode::Events`].
+    ///
+    /// Call [`VM::load_labels`] first. After a `NeedInput*` event, feed more
+    /// input (or close it) and call this again to carry on.
+    pub fn next_event(&mut self, instructions: &[Instruction<T>]) -> Result<IoEvent<T>> {
+        loop {
+            if let Some(event) = self.pending_event.take() {
+                return Ok(event);
+            }
+
+            match self.step(instructions)? {
+                StepOutcome::Continue => {}
+                StepOutcome::Halted => return Ok(IoEvent::Halted),
+                StepOutcome::NeedsInput => {
+                    return Ok(match instructions[self.instruction_ptr] {
+                        Instruction::ReadNumber => IoEvent::NeedInputNumber,
+                        _ => IoEvent::NeedInputChar,
+                    })
+                }
+            }
+        }
+    }
+
+    /// [`VM::step`] for running to completion: returns `false` once the
+    /// program has ended, and waiting for input is an [`InputPending`] error.
+    pub(crate) fn advance(&mut self, instructions: &[Instruction<T>]) -> Result<bool> {
+        match self.step(instructions)? {
+            StepOutcome::Continue => Ok(true),
+            StepOutcome::Halted => Ok(false),
+            StepOutcome::NeedsInput => Err(InputPending {
+                instruction: self.instruction_ptr,
+            }
+            .into()),
+        }
+    }
+
+    /// Executes the instruction at the instruction pointer, so that callers
+    /// can interleave execution with their own event loop.
+    ///
+    /// Call [`VM::load_labels`] before the first step.
+    pub fn step(&mut self, instructions: &[Instruction<T>]) -> Result<StepOutcome> {
+        if self.interrupt.as_ref().is_some_and(Interrupt::is_triggered) {
+            return Err(Interrupted {
+                instruction: self.instruction_ptr,
+            }
+            .into());
+        }
+        if let Some(fuel) = self.fuel.filter(|fuel| self.steps >= *fuel) {
+            return Err(FuelExhausted { steps: fuel }.into());
+        }
+
+        let stack_len = self.stack.len();
+
+        let Some(instruction) = instructions.get(self.instruction_ptr) else {
+            if self.implicit_exit {
+                return Ok(StepOutcome::Halted);
+            }
+
+            return Err(match self.instruction_ptr.checked_sub(1) {
+                Some(last) => WsError::RanOffEnd { last },
+                None => WsError::EmptyProgram,
+            });
+        };
+
+        let needed = instruction.stack_arguments();
+        if stack_len < needed {
+            return Err(self.underflow(instruction));
+        }
+
+        let io = self.io;
+        let waiting = match (self.fed_input(), io) {
+            (_, IoMode::Null) => false,
+            (Some(fed), _) => !fed.ready(instruction),
+            (None, IoMode::Events) => {
+                matches!(instruction, Instruction::ReadChar | Instruction::ReadNumber)
+            }
+            (None, IoMode::Terminal) => false,
+        };
+        if waiting {
+            return Ok(StepOutcome::NeedsInput);
+        }
+
+        let pushes = matches!(
+            instruction,
+            Instruction::Push(_) | Instruction::Duplicate | Instruction::Copy(_)
+        );
+        if let Some(limit) = self.max_stack.filter(|max| pushes && stack_len >= *max) {
+            return Err(StackOverflow {
+                limit,
+                instruction: self.instruction_ptr,
+            }
+            .into());
+        }
+
+        self.steps += 1;
+
+        if let Some(timeline) = &mut self.stack_timeline {
+            timeline.record(stack_len);
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer
+                .record(self.instruction_ptr, instruction, &self.stack)
+                .io_context("writing trace")?;
+        }
+
+        let ip = self.instruction_ptr;
+        for observer in &mut self.observers {
+            observer.before_instruction(ip, instruction, &self.stack);
+        }
+        let mut io_value = match instruction {
+            Instruction::OutputChar | Instruction::OutputNumber if !self.observers.is_empty() => {
+                self.stack.last().cloned()
+            }
+            _ => None,
+        };
+
+        match instruction {
+            Instruction::Push(number) => {
+                self.stack.push(number.clone());
+            }
+            Instruction::Duplicate => {
+                let element = self.peek_stack(instruction)?.clone();
+
+                self.stack.push(element);
+            }
+            Instruction::Copy(n) => {
+                let index = n.to_usize().filter(|n| *n < stack_len).ok_or_else(|| {
+                    WsError::InvalidCopy {
+                        index: n.to_string(),
+                        len: stack_len,
+                    }
+                })?;
+
+                self.stack.push(self.stack[stack_len - 1 - index].clone());
+            }
+            Instruction::Swap => {
+                self.stack.swap(stack_len - 1, stack_len - 2);
+            }
+            Instruction::Discard => {
+                self.pop_stack(instruction)?;
+            }
+            Instruction::Slide(n) => {
+                // like the reference implementation, a negative count slides
+                // nothing and a count past the bottom keeps only the top
+                let top = self.pop_stack(instruction)?;
+                let count = match n.is_negative() {
+                    true => 0,
+                    false => n.to_usize().unwrap_or(usize::MAX).min(self.stack.len()),
+                };
+
+                self.stack.truncate(self.stack.len() - count);
+                self.stack.push(top);
+            }
+            // the left operand is the one pushed first, the right one is on top
+            Instruction::Add => {
+                let right = self.pop_stack(instruction)?;
+                let left = self.pop_stack(instruction)?;
+                let result = left
+                    .add_with(&right, self.overflow)
+                    .ok_or_else(|| self.overflow_error(instruction, &left, &right))?;
+
+                self.stack.push(result);
+            }
+            Instruction::Substract => {
+                let right = self.pop_stack(instruction)?;
+                let left = self.pop_stack(instruction)?;
+                let result = left
+                    .sub_with(&right, self.overflow)
+                    .ok_or_else(|| self.overflow_error(instruction, &left, &right))?;
+
+                self.stack.push(result);
+            }
+            Instruction::Multiply => {
+                let right = self.pop_stack(instruction)?;
+                let left = self.pop_stack(instruction)?;
+                let result = left
+                    .mul_with(&right, self.overflow)
+                    .ok_or_else(|| self.overflow_error(instruction, &left, &right))?;
+
+                self.stack.push(result);
+            }
+            Instruction::Divide => {
+                let right = self.pop_stack(instruction)?;
+                let left = self.pop_stack(instruction)?;
+                if right.is_zero() {
+                    return Err(WsError::DivisionByZero {
+                        dividend: left.to_string(),
+                    });
+                }
+                let result = left
+                    .div_with(&right, self.division)
+                    .ok_or_else(|| self.overflow_error(instruction, &left, &right))?;
+
+                self.stack.push(result);
+            }
+            Instruction::Modulo => {
+                let right = self.pop_stack(instruction)?;
+                let left = self.pop_stack(instruction)?;
+                if right.is_zero() {
+                    return Err(WsError::ModuloByZero {
+                        dividend: left.to_string(),
+                    });
+                }
+                let result = left
+                    .rem_with(&right, self.division)
+                    .ok_or_else(|| self.overflow_error(instruction, &left, &right))?;
+
+                self.stack.push(result);
+            }
+            Instruction::HeapStore => {
+                let value = self.pop_stack(instruction)?;
+                let address = self.pop_stack(instruction)?;
+
+                self.store_heap(address, value)?;
+            }
+            Instruction::HeapRetrieve => {
+                let address = self.pop_stack(instruction)?;
+
+                let value = self.get_heap(address)?;
+
+                self.stack.push(value);
+            }
+            Instruction::MarkLocation(_) => {}
+            Instruction::Call(label) => {
+                if self
+                    .max_call_depth
+                    .is_some_and(|max| self.call_stack.len() >= max)
+                {
+                    return Err(WsError::CallDepthExceeded {
+                        instruction: self.instruction_ptr,
+                    });
+                }
+
+                self.call_stack.push(self.instruction_ptr);
+                self.jump(label)?;
+                tracing::trace!(
+                    label = ?label,
+                    depth = self.call_stack.len(),
+                    "called subroutine"
+                );
+            }
+            Instruction::Jump(label) => {
+                self.jump(label)?;
+            }
+            Instruction::JumpIfZero(label) => {
+                let top = self.pop_stack(instruction)?;
+
+                if top.is_zero() {
+                    self.jump(label)?;
+                }
+            }
+            Instruction::JumpIfNegative(label) => {
+                let top = self.pop_stack(instruction)?;
+
+                if top.is_negative() {
+                    self.jump(label)?;
+                }
+            }
+            Instruction::EndSubroutine => {
+                self.instruction_ptr = self
+                    .call_stack
+                    .pop()
+                    .ok_or(WsError::ReturnOutsideSubroutine)?;
+                tracing::trace!(depth = self.call_stack.len(), "returned from subroutine");
+            }
+            Instruction::EndProgram => {
+                if self.exit_code_from_stack {
+                    self.exit_code = Some(self.stack.pop().unwrap_or_else(T::zero));
+                }
+
+                tracing::debug!(steps = self.steps, "program ended");
+
+                self.notify_after(ip, instruction, None);
+                return Ok(StepOutcome::Halted);
+            }
+            Instruction::OutputChar => {
+                let element = self.pop_stack(instruction)?;
+
+                let chr = element.to_char().ok_or_else(|| WsError::InvalidChar {
+                    value: element.to_string(),
+                })?;
+
+                self.write_output(&chr.to_string())?;
+
+                if self.io == IoMode::Events {
+                    self.pending_event = Some(IoEvent::Output(chr));
+                }
+            }
+            Instruction::OutputNumber => {
+                let element = self.pop_stack(instruction)?;
+
+                self.write_output(&element.to_string())?;
+
+                if self.io == IoMode::Events {
+                    self.pending_event = Some(IoEvent::OutputNumber(element));
+                }
+            }
+            // both reads pop a heap address and store what they read there
+            Instruction::ReadChar | Instruction::ReadNumber if self.io == IoMode::Null => {
+                let address = self.pop_stack(instruction)?;
+
+                self.store_heap(address, T::zero())?;
+                io_value = Some(T::zero());
+            }
+            Instruction::ReadChar => {
+                let address = self.pop_stack(instruction)?;
+                let chr = self.host_io.read_char();
+
+                let value = match chr.io_context("reading a character")? {
+                    Some(chr) => T::from_char(chr),
+                    None => self.end_of_input()?,
+                };
+
+                self.store_heap(address, value.clone())?;
+                io_value = Some(value);
+            }
+            Instruction::ReadNumber => {
+                let address = self.pop_stack(instruction)?;
+                let mut line = String::new();
+
+                let read = self
+                    .host_io
+                    .read_line(&mut line)
+                    .io_context("reading line")?;
+
+                let value = if read == 0 {
+                    self.end_of_input()?
+                } else {
+                    line.trim()
+                        .parse()
+                        .map_err(|_| WsError::InvalidNumber(line.trim().to_string()))?
+                };
+
+                self.store_heap(address, value.clone())?;
+                io_value = Some(value);
+            }
+        };
+
+        self.instruction_ptr += 1;
+        self.notify_after(ip, instruction, io_value);
+
+        Ok(StepOutcome::Continue)
+    }
+
+    /// Tells the observers that the instruction at `ip` has run; `io_value`
+    /// is the value an output instruction popped or a read stored.
+    fn notify_after(&mut self, ip: usize, instruction: &Instruction<T>, io_value: Option<T>) {
+        if self.observers.is_empty() {
+            return;
+        }
+
+        let value = io_value.as_ref();
+        let jumped =
+            !matches!(instruction, Instruction::EndProgram) && self.instruction_ptr != ip + 1;
+
+        for observer in &mut self.observers {
+            if let Some(value) = value {
+                observer.on_io(ip, instruction, value);
+            }
+            if jumped {
+                observer.on_jump(ip, self.instruction_ptr);
+            }
+            observer.after_instruction(ip, instruction, &self.stack);
+        }
+    }
+
+    /// The value a read stores once the input is exhausted.
+    fn end_of_input(&self) -> Result<T> {
+        match self.eof {
+            EofBehavior::Error => Err(EndOfInput {
+                instruction: self.instruction_ptr,
+            }
+            .into()),
+            EofBehavior::MinusOne => Ok(T::zero() - cell_from_usize(1)?),
+            EofBehavior::Zero => Ok(T::zero()),
+        }
+    }
+
+    fn write_output(&mut self, text: &str) -> Result<()> {
+        if self.io == IoMode::Terminal {
+            self.host_io.write_str(text).io_context("writing output")?;
+        }
+
+        Ok(())
+    }
+
+    /// The error for `instruction` finding too few items on the stack.
+    fn underflow(&self, instruction: &Instruction<T>) -> WsError {
+        WsError::StackUnderflow {
+            instruction: self.instruction_ptr,
+            opcode: format!("{instruction:?}"),
+            needed: instruction.stack_arguments(),
+            available: self.stack.len(),
+        }
+    }
+
+    // step checks the stack depth before running an instruction, so these
+    // only fail if an instruction pops more than its stack_arguments
+    fn pop_stack(&mut self, instruction: &Instruction<T>) -> Result<T> {
+        self.stack.pop().ok_or_else(|| self.underflow(instruction))
+    }
+
+    fn peek_stack(&self, instruction: &Instruction<T>) -> Result<&T> {
+        self.stack.last().ok_or_else(|| self.underflow(instruction))
+    }
+
+    fn jump(&mut self, label: &String) -> Result<()> {
+        self.instruction_ptr = *self
+            .labels
+            .get(label)
+            .ok_or_else(|| WsError::UnknownLabel(label.clone()))?;
+
+        Ok(())
+    }
+
+    fn get_heap(&mut self, address: T) -> Result<T> {
+        let address = address
+            .to_usize()
+            .ok_or_else(|| WsError::InvalidAddress(address.to_string()))?;
+        self.check_heap_address(address)?;
+        if !self.heap.is_initialized(address) {
+            return Err(WsError::UninitializedRead {
+                address,
+                instruction: self.instruction_ptr,
+            });
+        }
+
+        if let Some(heatmap) = &mut self.heap_heatmap {
+            heatmap.record_read(address);
+        }
+
+        Ok(self.heap.get(address))
+    }
+
+    fn store_heap(&mut self, address: T, value: T) -> Result<()> {
+        let address = address
+            .to_usize()
+            .ok_or_else(|| WsError::InvalidAddress(address.to_string()))?;
+        self.check_heap_address(address)?;
+
+        if let Some(heatmap) = &mut self.heap_heatmap {
+            heatmap.record_write(address);
+        }
+
+        self.heap.set(address, value);
+
+        Ok(())
+    }
+
+    fn overflow_error(&self, instruction: &Instruction<T>, left: &T, right: &T) -> WsError {
+        WsError::Overflow {
+            instruction: self.instruction_ptr,
+            opcode: format!("{instruction:?}"),
+            left: left.to_string(),
+            right: right.to_string(),
+        }
+    }
+
+    fn check_heap_address(&self, address: usize) -> Result<()> {
+        match self.max_heap_size {
+            Some(max) if address >= max => Err(WsError::HeapOutOfBounds { address }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Reads one UTF-8 encoded character, `None` at end of input.
+fn read_char(input: &mut impl Read) -> io::Result<Option<char>> {
+    let mut bytes = [0; 4];
+
+    if input.read(&mut bytes[..1])? == 0 {
+        return Ok(None);
+    }
+
+    let len = match bytes[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 0,
+    };
+    if len > 1 {
+        input.read_exact(&mut bytes[1..len])?;
+    }
+
+    std::str::from_utf8(&bytes[..len])
+        .ok()
+        .and_then(|chr| chr.chars().next())
+        .map(Some)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8 in input"))
+}
+
+fn terminal() -> Box<dyn WsIo> {
+    Box::new(Terminal::default())
+}
+
+fn cell_from_usize<T: WhitespaceInt>(value: usize) -> Result<T> {
+    T::from_usize(value).ok_or(WsError::CellOverflow(value))
+}
+
+impl<T: WhitespaceInt> Default for VM<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a [`VM`], see [`VM::builder`].
+#[derive(Debug)]
+pub struct VMBuilder<T = i32> {
+    vm: VM<T>,
+    input: Option<Input>,
+    output: Option<Output>,
+}
+
+impl<T: WhitespaceInt> VMBuilder<T> {
+    /// Number of heap cells allocated up front; the heap grows past it on
+    /// demand.
+    pub fn heap_size(mut self, cells: usize) -> Self {
+        self.vm.heap = Heap::new(cells);
+        self
+    }
+
+    /// Addresses at or past `cells` are a heap overflow.
+    pub fn max_heap_size(mut self, cells: usize) -> Self {
+        self.vm.max_heap_size = Some(cells);
+        self
+    }
+
+    /// Pushing past `items` items is a [`StackOverflow`].
+    pub fn max_stack(mut self, items: usize) -> Self {
+        self.vm.max_stack = Some(items);
+        self
+    }
+
+    /// Calls nested deeper than `depth` are an error.
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.vm.max_call_depth = Some(depth);
+        self
+    }
+
+    /// Running more than `steps` instructions is a [`FuelExhausted`] error.
+    pub fn fuel(mut self, steps: u64) -> Self {
+        self.vm.fuel = Some(steps);
+        self
+    }
+
+    pub fn overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.vm.overflow = policy;
+        self
+    }
+
+    pub fn division(mut self, semantics: DivisionSemantics) -> Self {
+        self.vm.division = semantics;
+        self
+    }
+
+    pub fn eof(mut self, behavior: EofBehavior) -> Self {
+        self.vm.eof = behavior;
+        self
+    }
+
+    pub fn io(mut self, mode: IoMode) -> Self {
+        self.vm.io = mode;
+        self
+    }
+
+    /// Reads program input from `reader` instead of stdin; see
+    /// [`Terminal::redirect`].
+    pub fn input(mut self, reader: impl Read + Send + 'static) -> Self {
+        self.input = Some(Input::new(reader));
+        self
+    }
+
+    /// Writes program output to `writer` instead of stdout; see
+    /// [`Terminal::redirect`].
+    pub fn output(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.output = Some(Output::new(writer));
+        self
+    }
+
+    /// Performs all program I/O through `host` instead of the terminal,
+    /// unless `input` or `output` are given too.
+    pub fn host_io(mut self, host: impl WsIo) -> Self {
+        self.vm.host_io = Box::new(host);
+        self
+    }
+
+    /// Registers `observer` to be called back as instructions run.
+    pub fn observer(mut self, observer: impl Observer<T> + 'static) -> Self {
+        self.vm.observe(observer);
+        self
+    }
+
+    /// Treats running past the last instruction like EndProgram.
+    pub fn implicit_exit(mut self, enabled: bool) -> Self {
+        self.vm.implicit_exit = enabled;
+        self
+    }
+
+    /// Makes EndProgram pop the top of the stack into [`VM::exit_code`].
+    pub fn exit_code_from_stack(mut self, enabled: bool) -> Self {
+        self.vm.exit_code_from_stack = enabled;
+        self
+    }
+
+    pub fn build(mut self) -> VM<T> {
+        if self.input.is_some() || self.output.is_some() {
+            self.vm.host_io = Terminal::default().redirect(self.input, self.output);
+        }
+
+        self.vm
+    }
+}
+
+/// Tree-walking backend: runs the parsed instructions directly on a [`VM`].
+#[derive(Debug)]
+pub struct Interpreter<T = i32> {
+    vm: VM<T>,
+    program: Program<T>,
+}
+
+impl<T: WhitespaceInt> Interpreter<T> {
+    pub fn new(vm: VM<T>) -> Self {
+        Self {
+            vm,
+            program: Program::default(),
+        }
+    }
+
+    pub fn vm(&self) -> &VM<T> {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut VM<T> {
+        &mut self.vm
+    }
+
+    pub fn program(&self) -> &Program<T> {
+        &self.program
+    }
+}
+
+impl<T: WhitespaceInt> Default for Interpreter<T> {
+    fn default() -> Self {
+        Self::new(VM::default())
+    }
+}
+
+impl<T: WhitespaceInt> Executor for Interpreter<T> {
+    type Value = T;
+
+    fn load(&mut self, program: Program<T>) {
+        self.vm.load_labels(&program);
+        self.program = program;
+    }
+
+    fn step(&mut self) -> Result<bool> {
+        self.vm.advance(&self.program)
+    }
+
+    fn stack(&self) -> &[T] {
+        &self.vm.stack
+    }
+
+    fn heap(&self) -> &Heap<T> {
+        &self.vm.heap
+    }
+
+    fn instruction_ptr(&self) -> usize {
+        self.vm.instruction_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy() {
+        let mut vm: VM = VM::new();
+        vm.execute(&[
+            Instruction::Push(1),
+            Instruction::Push(2),
+            Instruction::Push(3),
+            Instruction::Copy(2),
+            Instruction::Copy(0),
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+
+        assert_eq!(vm.stack, vec![1, 2, 3, 1, 1]);
+        assert_eq!(vm.steps, 6);
+    }
+
+    #[test]
+    fn copy_out_of_range() {
+        for n in [-1, 1] {
+            let mut vm: VM = VM::new();
+            let result = vm.execute(&[Instruction::Push(1), Instruction::Copy(n)]);
+
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn slide() {
+        let mut vm: VM = VM::new();
+        vm.execute(&[
+            Instruction::Push(1),
+            Instruction::Push(2),
+            Instruction::Push(3),
+            Instruction::Push(4),
+            Instruction::Slide(2),
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+
+        assert_eq!(vm.stack, vec![1, 4]);
+    }
+
+    #[test]
+    fn slide_past_bottom_and_negative() {
+        let mut vm: VM = VM::new();
+        vm.execute(&[
+            Instruction::Push(1),
+            Instruction::Push(2),
+            Instruction::Slide(-3),
+            Instruction::Push(3),
+            Instruction::Slide(10),
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+
+        assert_eq!(vm.stack, vec![3]);
+    }
+
+    #[test]
+    fn slide_empty_stack() {
+        let mut vm: VM = VM::new();
+
+        assert!(vm.execute(&[Instruction::Slide(1)]).is_err());
+    }
+
+    #[test]
+    fn stack_underflow() {
+        for instruction in [
+            Instruction::Duplicate,
+            Instruction::Swap,
+            Instruction::Add,
+            Instruction::Divide,
+            Instruction::HeapRetrieve,
+            Instruction::JumpIfZero(" ".to_string()),
+            Instruction::OutputNumber,
+            Instruction::ReadChar,
+        ] {
+            let mut vm: VM = VM::new();
+            let result = vm.execute(&[instruction, Instruction::EndProgram]);
+
+            assert!(result.unwrap_err().to_string().contains("stack has 0"));
+        }
+
+        let mut vm: VM = VM::new();
+        vm.stack.push(1);
+        let result = vm.execute(&[Instruction::Swap]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "stack underflow: Swap at instruction 0 needs 2 items but the stack has 1"
+        );
+    }
+
+    #[test]
+    fn conditional_jumps_pop() {
+        let mut vm: VM = VM::new();
+        vm.execute(&[
+            Instruction::Push(5),
+            Instruction::Push(0),
+            Instruction::JumpIfZero(" ".to_string()),
+            Instruction::Push(1),
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Push(1),
+            Instruction::JumpIfNegative("\t".to_string()),
+            Instruction::Push(-1),
+            Instruction::JumpIfNegative("\t".to_string()),
+            Instruction::Push(2),
+            Instruction::MarkLocation("\t".to_string()),
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+
+        assert_eq!(vm.stack, vec![5]);
+    }
+
+    #[test]
+    fn reads_store_at_address() {
+        let program = [
+            Instruction::Push(3),
+            Instruction::ReadChar,
+            Instruction::Push(4),
+            Instruction::ReadNumber,
+            Instruction::EndProgram,
+        ];
+
+        let mut vm: VM = VM::new();
+        vm.host_io = Box::new(Streams::new("a-5\n".as_bytes(), io::sink()));
+        vm.execute(&program).unwrap();
+        assert!(vm.stack.is_empty());
+        assert_eq!((vm.heap.get(3), vm.heap.get(4)), (97, -5));
+
+        let mut vm: VM = VM::builder().io(IoMode::Null).build();
+        vm.heap.set(3, 1);
+        vm.execute(&program).unwrap();
+        assert!(vm.stack.is_empty());
+        assert_eq!((vm.heap.get(3), vm.heap.get(4)), (0, 0));
+    }
+
+    #[test]
+    fn subroutine_keeps_data_stack() {
+        let mut vm: VM = VM::new();
+        vm.execute(&[
+            Instruction::Call(" ".to_string()),
+            Instruction::Push(2),
+            Instruction::EndProgram,
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Push(1),
+            Instruction::EndSubroutine,
+        ])
+        .unwrap();
+
+        assert_eq!(vm.stack, vec![1, 2]);
+        assert!(vm.call_stack.is_empty());
+    }
+
+    #[test]
+    fn max_stack() {
+        let mut vm: VM = VM::new();
+        vm.max_stack = Some(3);
+
+        let result = vm.execute(&[
+            Instruction::Push(1),
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Duplicate,
+            Instruction::Jump(" ".to_string()),
+        ]);
+
+        let error = result.unwrap_err();
+        assert!(matches!(
+            error,
+            WsError::StackOverflow(StackOverflow {
+                limit: 3,
+                instruction: 2
+            })
+        ));
+        assert_eq!(vm.stack.len(), 3);
+    }
+
+    #[test]
+    fn fuel() {
+        let program = [
+            Instruction::Push(1),
+            Instruction::Push(2),
+            Instruction::Add,
+            Instruction::EndProgram,
+        ];
+
+        let mut vm: VM = VM::new();
+        let error = vm.execute_with_fuel(&program, 2).unwrap_err();
+        assert!(matches!(
+            error,
+            WsError::FuelExhausted(FuelExhausted { steps: 2 })
+        ));
+        assert_eq!(vm.stack, vec![1, 2]);
+
+        vm.execute_with_fuel(&program, 2).unwrap();
+        assert_eq!(vm.stack, vec![3]);
+    }
+
+    #[test]
+    fn interrupt() {
+        let mut vm: VM = VM::new();
+        vm.interrupt = Some(Interrupt::after(Duration::from_millis(10)));
+
+        let result = vm.execute(&[
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Jump(" ".to_string()),
+        ]);
+
+        assert!(matches!(result.unwrap_err(), WsError::Interrupted(_)));
+
+        let manual = Interrupt::default();
+        let timed = Interrupt::after(Duration::from_secs(3600));
+        assert!(!manual.is_triggered() && !timed.is_triggered());
+        timed.clone().trigger();
+        assert!(timed.is_triggered());
+        assert!(Interrupt::after(Duration::ZERO).is_triggered());
+    }
+
+    #[test]
+    fn exit_code_from_stack() {
+        let mut vm: VM = VM::new();
+        vm.exit_code_from_stack = true;
+        vm.execute(&[
+            Instruction::Push(1),
+            Instruction::Push(3),
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+
+        assert_eq!(vm.exit_code, Some(3));
+        assert_eq!(vm.stack, vec![1]);
+    }
+
+    #[test]
+    fn run_off_end() {
+        let program = [Instruction::Push(1), Instruction::Push(2)];
+
+        let mut vm: VM = VM::new();
+        assert_eq!(
+            vm.execute(&program).unwrap_err().to_string(),
+            "ran off the end of the program after instruction 1 without reaching EndProgram"
+        );
+
+        let mut vm: VM = VM::new();
+        vm.implicit_exit = true;
+        vm.execute(&program).unwrap();
+        assert_eq!(vm.stack, vec![1, 2]);
+    }
+
+    #[test]
+    fn reset() {
+        let mut vm: VM = VM::builder().output(io::sink()).build();
+        vm.capture_output();
+
+        let first = [
+            Instruction::Push(1),
+            Instruction::Push(7),
+            Instruction::HeapStore,
+            Instruction::Push(3),
+            Instruction::OutputNumber,
+            Instruction::Push(1),
+            Instruction::EndProgram,
+        ];
+        let second = [
+            Instruction::Jump(" ".to_string()),
+            Instruction::Push(1),
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Push(2),
+            Instruction::EndProgram,
+        ];
+
+        vm.execute(&first).unwrap();
+        vm.reset();
+        vm.execute(&second).unwrap();
+
+        assert_eq!(vm.stack, vec![2]);
+        assert_eq!(vm.heap.get(1), 0);
+        assert_eq!(vm.captured_output(), Some(""));
+
+        vm.reset();
+        vm.execute(&second).unwrap();
+        assert_eq!(vm.stack, vec![2]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let program = [
+            Instruction::Push(3),
+            Instruction::Push(1 << 20),
+            Instruction::Push(9),
+            Instruction::HeapStore,
+            Instruction::Call(" ".to_string()),
+            Instruction::EndProgram,
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Push(4),
+            Instruction::EndSubroutine,
+        ];
+
+        let mut vm: VM = VM::new();
+        vm.execute_with_fuel(&program, 6).unwrap_err();
+
+        let json = serde_json::to_string(&vm).unwrap();
+        let mut restored: VM = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.call_stack, vec![4]);
+        assert_eq!(restored.heap.get(1 << 20), 9);
+
+        restored.execute(&program).unwrap();
+        assert_eq!(restored.stack, vec![3, 4]);
+    }
+
+    #[test]
+    fn max_call_depth() {
+        let mut vm: VM = VM::new();
+        vm.max_call_depth = Some(100);
+
+        let result = vm.execute(&[
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Call(" ".to_string()),
+        ]);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "maximum call depth exceeded at instruction 1"
+        );
+        assert_eq!(vm.call_stack.len(), 100);
+    }
+
+    #[test]
+    fn return_without_call() {
+        let mut vm: VM = VM::new();
+        vm.stack.push(0);
+
+        assert!(vm.execute(&[Instruction::EndSubroutine]).is_err());
+    }
+
+    #[test]
+    fn large_heap_addresses() {
+        let mut vm: VM<i64> = VM::new();
+        vm.execute(&[
+            Instruction::Push(1 << 40),
+            Instruction::Push(5),
+            Instruction::HeapStore,
+            Instruction::Push(1 << 40),
+            Instruction::HeapRetrieve,
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+
+        assert_eq!(vm.stack, vec![5]);
+    }
+
+    #[test]
+    fn uninitialized_heap_read() {
+        let mut vm: VM = VM::new();
+        vm.heap.track_initialized();
+
+        let result = vm.execute(&[
+            Instruction::Push(1),
+            Instruction::Push(5),
+            Instruction::HeapStore,
+            Instruction::Push(1),
+            Instruction::HeapRetrieve,
+            Instruction::Push(2),
+            Instruction::HeapRetrieve,
+            Instruction::EndProgram,
+        ]);
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "read of uninitialized heap address 2 at instruction 6"
+        );
+        assert_eq!(vm.stack, vec![5]);
+    }
+
+    #[test]
+    fn max_heap_size() {
+        let mut vm: VM = VM::with_heap_size(4);
+        vm.max_heap_size = Some(8);
+
+        vm.execute(&[
+            Instruction::Push(7),
+            Instruction::Push(1),
+            Instruction::HeapStore,
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+        assert_eq!(vm.heap.get(7), 1);
+        assert!(vm.write_heap_string(6, "ab").is_err());
+
+        let mut vm: VM = VM::with_heap_size(4);
+        vm.max_heap_size = Some(8);
+        let result = vm.execute(&[
+            Instruction::Push(8),
+            Instruction::HeapRetrieve,
+            Instruction::EndProgram,
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn operand_order() {
+        for (instruction, expected) in [
+            (Instruction::Substract, 5),
+            (Instruction::Divide, 3),
+            (Instruction::Modulo, 1),
+        ] {
+            let mut vm: VM = VM::new();
+            vm.execute(&[
+                Instruction::Push(7),
+                Instruction::Push(2),
+                instruction,
+                Instruction::EndProgram,
+            ])
+            .unwrap();
+
+            assert_eq!(vm.stack, vec![expected]);
+        }
+    }
+
+    #[test]
+    fn division_semantics() {
+        let program = [
+            Instruction::Push(-7),
+            Instruction::Push(2),
+            Instruction::Divide,
+            Instruction::Push(-7),
+            Instruction::Push(2),
+            Instruction::Modulo,
+            Instruction::EndProgram,
+        ];
+
+        let mut vm: VM = VM::new();
+        vm.execute(&program).unwrap();
+        assert_eq!(vm.stack, vec![-4, 1]);
+
+        let mut vm: VM = VM::new();
+        vm.division = DivisionSemantics::Truncate;
+        vm.execute(&program).unwrap();
+        assert_eq!(vm.stack, vec![-3, -1]);
+    }
+
+    #[test]
+    fn division_by_zero() {
+        for instruction in [Instruction::Divide, Instruction::Modulo] {
+            let mut vm: VM = VM::new();
+            let result = vm.execute(&[
+                Instruction::Push(7),
+                Instruction::Push(0),
+                instruction,
+                Instruction::EndProgram,
+            ]);
+
+            assert!(result.unwrap_err().to_string().contains("zero"));
+        }
+
+        let mut vm: VM = VM::new();
+        let result = vm.execute(&[
+            Instruction::Push(i32::MIN),
+            Instruction::Push(-1),
+            Instruction::Modulo,
+            Instruction::EndProgram,
+        ]);
+        assert!(result.unwrap_err().to_string().contains("overflow"));
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let program = [
+            Instruction::Push(i32::MAX),
+            Instruction::Push(1),
+            Instruction::Add,
+            Instruction::EndProgram,
+        ];
+
+        let mut vm: VM = VM::new();
+        let error = vm.execute(&program).unwrap_err();
+        assert!(error.to_string().ends_with("at instruction 2"));
+
+        let mut vm: VM = VM::new();
+        vm.overflow = OverflowPolicy::Wrap;
+        vm.execute(&program).unwrap();
+        assert_eq!(vm.stack, vec![i32::MIN]);
+
+        let mut vm: VM = VM::new();
+        vm.overflow = OverflowPolicy::Saturate;
+        vm.execute(&program).unwrap();
+        assert_eq!(vm.stack, vec![i32::MAX]);
+    }
+
+    #[test]
+    fn preload_args() {
+        let mut vm: VM = VM::new();
+        vm.preload_args(&["ab".to_string(), "c".to_string()])
+            .unwrap();
+
+        assert_eq!(vm.stack, vec![3, 0, 2]);
+        let heap: Vec<i32> = (0..5).map(|address| vm.heap.get(address)).collect();
+        assert_eq!(heap, ['a' as i32, 'b' as i32, 0, 'c' as i32, 0]);
+    }
+
+    #[test]
+    fn args_conventions() {
+        let args = ["ab".to_string(), "c".to_string()];
+
+        let mut vm: VM = VM::new();
+        vm.preload_args_with(&args, ArgsConvention::Heap { address: 10 })
+            .unwrap();
+        assert!(vm.stack.is_empty());
+        assert_eq!(vm.heap.get(10), 2);
+        assert_eq!(vm.read_heap_string(11).unwrap(), "ab");
+        assert_eq!(vm.read_heap_string(14).unwrap(), "c");
+
+        let mut vm: VM = VM::new();
+        vm.preload_args_with(&args, ArgsConvention::Stack).unwrap();
+        let (a, b, c) = ('a' as i32, 'b' as i32, 'c' as i32);
+        assert_eq!(vm.stack, vec![0, c, 0, b, a, 2]);
+        assert_eq!(vm.heap.cells(), vec![]);
+
+        let mut vm: VM = VM::new();
+        vm.preload_args_with(&args, ArgsConvention::Argv { address: 100 })
+            .unwrap();
+        assert_eq!(vm.stack, vec![103, 100, 2]);
+    }
+
+    #[test]
+    fn preload_heap() {
+        let mut vm: VM = VM::new();
+
+        assert_eq!(vm.preload_heap(2000, [4, 5, 6]).unwrap(), 2003);
+        assert_eq!(vm.heap.get(2001), 5);
+    }
+
+    #[test]
+    fn heap_strings() {
+        let mut vm: VM = VM::with_heap_size(16);
+
+        let end = vm.write_heap_string(2, "hé").unwrap();
+        assert_eq!(end, 5);
+        assert_eq!(vm.read_heap_string(2).unwrap(), "hé");
+
+        let end = vm.write_prefixed_heap_string(end, "xyz").unwrap();
+        assert_eq!(end, 9);
+        assert_eq!(vm.read_prefixed_heap_string(5).unwrap(), "xyz");
+
+        vm.write_heap_string(14, "ab").unwrap();
+        assert_eq!(vm.read_heap_string(14).unwrap(), "ab");
+        assert!(vm.read_heap_string(9).is_ok_and(|s| s.is_empty()));
+    }
+
+    #[test]
+    fn huge_prefixed_string_length() {
+        let mut vm: VM<i128> = VM::new();
+        vm.heap.set(3, usize::MAX as i128);
+        assert!(matches!(
+            vm.read_prefixed_heap_string(3),
+            Err(WsError::AddressOverflow)
+        ));
+
+        let mut vm: VM = VM::builder().max_heap_size(16).build();
+        vm.heap.set(0, i32::MAX);
+        assert!(matches!(
+            vm.read_prefixed_heap_string(0),
+            Err(WsError::HeapOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn builder() {
+        let mut vm: VM = VM::builder()
+            .heap_size(4)
+            .max_stack(2)
+            .fuel(4)
+            .input("7\n".as_bytes())
+            .output(std::io::sink())
+            .build();
+        assert_eq!(vm.max_stack, Some(2));
+
+        let result = vm.execute(&[
+            Instruction::Push(0),
+            Instruction::ReadNumber,
+            Instruction::Push(0),
+            Instruction::HeapRetrieve,
+            Instruction::Duplicate,
+            Instruction::EndProgram,
+        ]);
+
+        assert_eq!(vm.stack, vec![7]);
+        assert!(matches!(
+            result.unwrap_err(),
+            WsError::FuelExhausted(FuelExhausted { steps: 4 })
+        ));
+    }
+
+    #[test]
+    fn host_io() {
+        #[derive(Default)]
+        struct Script {
+            input: Vec<char>,
+            output: Vec<String>,
+        }
+
+        impl WsIo for Arc<std::sync::Mutex<Script>> {
+            fn read_char(&mut self) -> io::Result<Option<char>> {
+                Ok(self.lock().unwrap().input.pop())
+            }
+
+            fn read_line(&mut self, _line: &mut String) -> io::Result<usize> {
+                Ok(0)
+            }
+
+            fn write_str(&mut self, text: &str) -> io::Result<()> {
+                self.lock().unwrap().output.push(text.to_string());
+                Ok(())
+            }
+        }
+
+        let script = Arc::new(std::sync::Mutex::new(Script {
+            input: vec!['b', 'a'],
+            ..Script::default()
+        }));
+        let mut vm: VM = VM::builder()
+            .host_io(script.clone())
+            .eof(EofBehavior::Zero)
+            .build();
+        vm.execute(&[
+            Instruction::Push(0),
+            Instruction::ReadChar,
+            Instruction::Push(0),
+            Instruction::HeapRetrieve,
+            Instruction::OutputChar,
+            Instruction::Push(1),
+            Instruction::ReadChar,
+            Instruction::Push(1),
+            Instruction::HeapRetrieve,
+            Instruction::OutputNumber,
+            Instruction::Push(2),
+            Instruction::ReadNumber,
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+
+        assert!(vm.stack.is_empty());
+        assert_eq!(vm.heap.get(2), 0);
+        assert_eq!(script.lock().unwrap().output, vec!["a", "98"]);
+
+        let mut streams = Streams::new("7\n".as_bytes(), Vec::new());
+        let mut line = String::new();
+        streams.read_line(&mut line).unwrap();
+        streams.write_str(&line).unwrap();
+        assert_eq!(streams.writer, b"7\n");
+    }
+
+    #[test]
+    fn channels() {
+        let (to_program, input) = std::sync::mpsc::channel();
+        let (output, from_program) = std::sync::mpsc::channel();
+        let mut vm: VM = VM::builder()
+            .host_io(Streams::channels(input, output))
+            .build();
+
+        let program = std::thread::spawn(move || {
+            vm.execute(&[
+                Instruction::Push(0),
+                Instruction::ReadNumber,
+                Instruction::Push(0),
+                Instruction::HeapRetrieve,
+                Instruction::Push(1),
+                Instruction::Add,
+                Instruction::OutputNumber,
+                Instruction::Push(0),
+                Instruction::ReadChar,
+                Instruction::Push(0),
+                Instruction::HeapRetrieve,
+                Instruction::OutputChar,
+                Instruction::EndProgram,
+            ])
+        });
+
+        for byte in b"41\n" {
+            to_program.send(*byte).unwrap();
+        }
+        assert_eq!(from_program.recv().unwrap(), b'4');
+        assert_eq!(from_program.recv().unwrap(), b'2');
+
+        for byte in "é".bytes() {
+            to_program.send(byte).unwrap();
+        }
+        program.join().unwrap().unwrap();
+        assert_eq!(from_program.iter().collect::<Vec<_>>(), "é".as_bytes());
+    }
+
+    #[test]
+    fn execute_capture() {
+        let mut vm: VM = VM::builder().exit_code_from_stack(true).build();
+        let run = vm.execute_capture(
+            &[
+                Instruction::Push(0),
+                Instruction::ReadChar,
+                Instruction::Push(0),
+                Instruction::HeapRetrieve,
+                Instruction::OutputChar,
+                Instruction::Push(1),
+                Instruction::ReadNumber,
+                Instruction::Push(1),
+                Instruction::HeapRetrieve,
+                Instruction::EndProgram,
+            ],
+            "x42\n",
+        );
+
+        assert!(run.is_success());
+        assert_eq!(run.output, b"x");
+        assert_eq!(run.exit_code, Some(42));
+        assert!(run.stack.is_empty());
+        assert_eq!(run.heap.get(0), 120);
+        assert!((vm.host_io.as_ref() as &dyn Any).is::<Terminal>());
+
+        let mut vm: VM = VM::new();
+        let run = vm.execute_capture(&[Instruction::Push(1), Instruction::EndProgram], "");
+        assert_eq!(run.exit_code, Some(0));
+        assert_eq!(run.stack, vec![1]);
+    }
+
+    #[test]
+    fn execute_capture_error() {
+        let mut vm: VM = VM::new();
+        let run = vm.execute_capture(
+            &[
+                Instruction::Push(72),
+                Instruction::Duplicate,
+                Instruction::OutputChar,
+                Instruction::Discard,
+                Instruction::Discard,
+            ],
+            "",
+        );
+
+        assert!(matches!(run.error, Some(WsError::StackUnderflow { .. })));
+        assert_eq!(run.output, b"H");
+        assert_eq!(run.exit_code, None);
+        assert!(run.stack.is_empty());
+    }
+
+    #[test]
+    fn worker_thread() {
+        struct Seen(Arc<AtomicBool>);
+
+        impl Observer<i32> for Seen {
+            fn on_io(&mut self, _ip: usize, _instruction: &Instruction<i32>, _value: &i32) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let seen = Arc::new(AtomicBool::new(false));
+        let mut vm: VM = VM::builder()
+            .input(io::Cursor::new("7\n"))
+            .output(io::sink())
+            .observer(Seen(Arc::clone(&seen)))
+            .build();
+        vm.tracer = Some(Tracer::new(io::sink(), None));
+        vm.capture_output();
+
+        let program = [
+            Instruction::Push(0),
+            Instruction::ReadNumber,
+            Instruction::Push(0),
+            Instruction::HeapRetrieve,
+            Instruction::OutputNumber,
+            Instruction::EndProgram,
+        ];
+        let vm = std::thread::spawn(move || {
+            vm.execute(&program).unwrap();
+            vm
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(vm.captured_output(), Some("7"));
+        assert!(seen.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn duplicate_labels() {
+        let program: Program = vec![
+            Instruction::Jump(" ".to_string()),
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Push(1),
+            Instruction::EndProgram,
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Push(2),
+            Instruction::EndProgram,
+        ]
+        .into();
+        let mut vm: VM = VM::new();
+        vm.execute(&program).unwrap();
+
+        assert_eq!(program.labels()[" "], 1);
+        assert_eq!(vm.stack, vec![1]);
+    }
+
+    #[test]
+    fn snapshots() {
+        let program = [
+            Instruction::Push(1),
+            Instruction::Push(2),
+            Instruction::Add,
+            Instruction::Push(0),
+            Instruction::Swap,
+            Instruction::HeapStore,
+            Instruction::EndProgram,
+        ];
+        let mut vm: VM = VM::builder().max_stack(8).build();
+        vm.load_labels(&program);
+        vm.step(&program).unwrap();
+
+        let snapshot = vm.snapshot();
+        let mut branch = vm.clone();
+        branch.execute(&program).unwrap();
+        assert_eq!(branch.heap.get(0), 3);
+        assert_eq!(branch.max_stack, Some(8));
+
+        vm.execute(&program).unwrap();
+        vm.restore(snapshot);
+        assert_eq!(vm.stack, vec![1]);
+        assert_eq!(vm.heap.get(0), 0);
+        assert_eq!(vm.steps, 1);
+
+        vm.execute(&program).unwrap();
+        assert_eq!(vm.heap.get(0), 3);
+    }
+
+    #[test]
+    fn fed_input() {
+        let program = [
+            Instruction::Push(0),
+            Instruction::ReadChar,
+            Instruction::Push(1),
+            Instruction::ReadNumber,
+            Instruction::Push(2),
+            Instruction::ReadChar,
+            Instruction::EndProgram,
+        ];
+        let mut vm: VM = VM::builder().eof(EofBehavior::MinusOne).build();
+        vm.load_labels(&program);
+
+        vm.feed_input("a1");
+        for _ in 0..3 {
+            assert_eq!(vm.step(&program).unwrap(), StepOutcome::Continue);
+        }
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::NeedsInput);
+        assert_eq!(vm.steps, 3);
+
+        vm.feed_input("2\n");
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::Continue);
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::Continue);
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::NeedsInput);
+
+        vm.close_input();
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::Continue);
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::Halted);
+        assert!(vm.stack.is_empty());
+        assert_eq!(
+            (0..3).map(|a| vm.heap.get(a)).collect::<Vec<_>>(),
+            [97, 12, -1]
+        );
+
+        let mut vm: VM = VM::new();
+        vm.feed_input("x");
+        let error = vm.execute(&program).unwrap_err();
+        assert!(matches!(
+            error,
+            WsError::InputPending(InputPending { instruction: 3 })
+        ));
+    }
+
+    #[test]
+    fn timeline_skips_waiting() {
+        let program = [
+            Instruction::Push(0),
+            Instruction::ReadChar,
+            Instruction::EndProgram,
+        ];
+        let mut vm: VM = VM::new();
+        vm.stack_timeline = Some(StackTimeline::new(1));
+        vm.load_labels(&program);
+
+        vm.feed_input("");
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::Continue);
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::NeedsInput);
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::NeedsInput);
+        vm.feed_input("a");
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::Continue);
+        assert_eq!(vm.step(&program).unwrap(), StepOutcome::Halted);
+
+        let timeline = vm.stack_timeline.unwrap();
+        assert_eq!(timeline.samples(), &[(0, 0), (1, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn events() {
+        let program = [
+            Instruction::Push(0),
+            Instruction::ReadNumber,
+            Instruction::Push(0),
+            Instruction::HeapRetrieve,
+            Instruction::OutputNumber,
+            Instruction::Push(1),
+            Instruction::ReadChar,
+            Instruction::Push(1),
+            Instruction::HeapRetrieve,
+            Instruction::OutputChar,
+            Instruction::EndProgram,
+        ];
+        let mut vm: VM = VM::builder().io(IoMode::Events).build();
+        vm.load_labels(&program);
+
+        assert_eq!(vm.next_event(&program).unwrap(), IoEvent::NeedInputNumber);
+        vm.feed_input("12\n");
+        assert_eq!(vm.next_event(&program).unwrap(), IoEvent::OutputNumber(12));
+        assert_eq!(vm.next_event(&program).unwrap(), IoEvent::NeedInputChar);
+        vm.feed_input("é");
+        assert_eq!(vm.next_event(&program).unwrap(), IoEvent::Output('é'));
+        assert_eq!(vm.next_event(&program).unwrap(), IoEvent::Halted);
+        assert!(vm.stack.is_empty());
+        assert_eq!(vm.heap.get(0), 12);
+    }
+
+    #[test]
+    fn injected_input() {
+        let mut vm: VM = VM::new();
+        vm.host_io = Box::new(Streams::new("é-12\n".as_bytes(), io::sink()));
+        vm.eof = EofBehavior::MinusOne;
+        vm.execute(&[
+            Instruction::Push(0),
+            Instruction::ReadChar,
+            Instruction::Push(1),
+            Instruction::ReadNumber,
+            Instruction::Push(2),
+            Instruction::ReadChar,
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+
+        assert!(vm.stack.is_empty());
+        assert_eq!(
+            (0..3).map(|a| vm.heap.get(a)).collect::<Vec<_>>(),
+            [233, -12, -1]
+        );
+    }
 }
@@ -1,3 +1,9 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::diagnostics::Span;
+use crate::error::WsError;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Token {
     Space,
@@ -5,28 +11,293 @@ pub enum Token {
     LineFeed,
 }
 
+impl Token {
+    /// The whitespace character the token was lexed from.
+    pub fn as_char(&self) -> char {
+        match self {
+            Token::Space => ' ',
+            Token::Tab => '\t',
+            Token::LineFeed => '\n',
+        }
+    }
+
+    /// The letter standing for the token in the letters syntax.
+    pub fn as_letter(&self) -> char {
+        match self {
+            Token::Space => 'S',
+            Token::Tab => 'T',
+            Token::LineFeed => 'L',
+        }
+    }
+}
+
+/// The characters a program is written with, one per token.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Alphabet {
+    pub space: char,
+    pub tab: char,
+    pub line_feed: char,
+}
+
+impl Alphabet {
+    /// Real whitespace.
+    pub const WHITESPACE: Alphabet = Alphabet {
+        space: ' ',
+        tab: '\t',
+        line_feed: '\n',
+    };
+
+    /// The letters `S`, `T` and `L`.
+    pub const LETTERS: Alphabet = Alphabet {
+        space: 'S',
+        tab: 'T',
+        line_feed: 'L',
+    };
+
+    /// The token `chr` stands for, if any.
+    pub fn token(&self, chr: char) -> Option<Token> {
+        match chr {
+            chr if chr == self.space => Some(Token::Space),
+            chr if chr == self.tab => Some(Token::Tab),
+            chr if chr == self.line_feed => Some(Token::LineFeed),
+            _ => None,
+        }
+    }
+
+    /// Translates a program written with this alphabet into real whitespace,
+    /// dropping every other character.
+    pub fn to_whitespace(&self, input: &str) -> String {
+        strip_shebang(input)
+            .chars()
+            .filter_map(|chr| self.token(chr))
+            .map(|token| token.as_char())
+            .collect()
+    }
+}
+
+impl Default for Alphabet {
+    fn default() -> Self {
+        Alphabet::WHITESPACE
+    }
+}
+
+/// Parses mappings like `space=S,tab=T,lf=L`; tokens that aren't mentioned
+/// keep their whitespace character.
+impl FromStr for Alphabet {
+    type Err = WsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: String| Err(WsError::InvalidAlphabet(message));
+        let mut alphabet = Alphabet::WHITESPACE;
+
+        for mapping in s.split(',') {
+            let Some((name, value)) = mapping.split_once('=') else {
+                return invalid(format!("expected `TOKEN=CHAR`, found `{mapping}`"));
+            };
+            let mut chars = value.chars();
+            let (Some(chr), None) = (chars.next(), chars.next()) else {
+                return invalid(format!(
+                    "`{name}` must map to a single character, not `{value}`"
+                ));
+            };
+
+            match name.trim() {
+                "space" => alphabet.space = chr,
+                "tab" => alphabet.tab = chr,
+                "lf" => alphabet.line_feed = chr,
+                name => {
+                    return invalid(format!(
+                        "unknown token `{name}`; expected `space`, `tab` or `lf`"
+                    ))
+                }
+            }
+        }
+
+        let Alphabet {
+            space,
+            tab,
+            line_feed,
+        } = alphabet;
+        if space == tab || space == line_feed || tab == line_feed {
+            return invalid("two tokens map to the same character".to_string());
+        }
+
+        Ok(alphabet)
+    }
+}
+
+/// What counts as a line feed.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum LineEndings {
+    /// Only LF; CR is ignored like any comment, so CR LF reads as one line
+    /// feed.
+    #[default]
+    Lf,
+    /// LF, CR LF, and a CR on its own as written by old Macs.
+    Any,
+}
+
+/// How the lexer treats whitespace characters other than space, tab and
+/// line feed, which are comments by default.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct LexerOptions {
+    pub line_endings: LineEndings,
+    /// Token a vertical tab stands for, for dialects that use it.
+    pub vertical_tab: Option<Token>,
+}
+
+/// Removes a leading `#!` line, so that programs can be run as scripts.
+pub fn strip_shebang(input: &str) -> &str {
+    match input.strip_prefix("#!") {
+        Some(rest) => rest.split_once('\n').map_or("", |(_, program)| program),
+        None => input,
+    }
+}
+
+/// Byte ranges of the contents of the ` ```whitespace ` fenced code blocks of
+/// a Markdown document, in order.
+///
+/// A block ends with the line feed of its last line, which is part of the
+/// program like every other line feed.
+pub fn markdown_blocks(input: &str) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut open = None;
+    let mut offset = 0;
+
+    for line in input.split_inclusive('\n') {
+        let fence = line.trim_start().strip_prefix("```");
+        match (open, fence) {
+            (None, Some(info)) if info.split_whitespace().next() == Some("whitespace") => {
+                open = Some(offset + line.len());
+            }
+            (Some(start), Some(rest)) if rest.trim().is_empty() => {
+                blocks.push(start..offset);
+                open = None;
+            }
+            _ => {}
+        }
+
+        offset += line.len();
+    }
+    // like CommonMark, an unclosed block runs to the end of the document
+    if let Some(start) = open {
+        blocks.push(start..input.len());
+    }
+
+    blocks
+}
+
+/// The contents of every ` ```whitespace ` block of a Markdown document, put
+/// together into one program.
+pub fn extract_markdown(input: &str) -> String {
+    markdown_blocks(input)
+        .into_iter()
+        .map(|block| &input[block])
+        .collect()
+}
+
+/// Byte offset where the program of a polyglot file starts: the start of the
+/// line after the first line containing `marker`.
+pub fn after_marker(input: &str, marker: &str) -> Option<usize> {
+    let at = input.find(marker)?;
+
+    Some(
+        input[at..]
+            .find('\n')
+            .map_or(input.len(), |end| at + end + 1),
+    )
+}
+
+/// Source position of each letter kept by [`letters_to_whitespace`].
+pub fn letter_spans(input: &str) -> Vec<Span> {
+    Lexer::with_alphabet(input, Alphabet::LETTERS).spans()
+}
+
+/// Translates a program written with the letters `S`, `T` and `L` into real
+/// whitespace, dropping every other character.
+pub fn letters_to_whitespace(input: &str) -> String {
+    Alphabet::LETTERS.to_whitespace(input)
+}
+
 #[derive(Debug)]
 pub struct Lexer {
     input: String,
+    alphabet: Alphabet,
+    options: LexerOptions,
 }
 
 impl Lexer {
     pub fn new(input: impl Into<String>) -> Self {
+        Self::with_alphabet(input, Alphabet::WHITESPACE)
+    }
+
+    /// A lexer for programs written with the characters of `alphabet`.
+    pub fn with_alphabet(input: impl Into<String>, alphabet: Alphabet) -> Self {
         Self {
             input: input.into(),
+            alphabet,
+            options: LexerOptions::default(),
         }
     }
 
+    pub fn options(mut self, options: LexerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn lex(&self) -> Vec<Token> {
-        self.input
-            .chars()
-            .filter_map(|chr| match chr {
-                ' ' => Some(Token::Space),
-                '\t' => Some(Token::Tab),
-                '\n' => Some(Token::LineFeed),
+        let tokens: Vec<Token> = self.scan().into_iter().map(|(token, _)| token).collect();
+
+        tracing::debug!(tokens = tokens.len(), "lexed program");
+        tokens
+    }
+
+    /// Source position of each token returned by [`Lexer::lex`].
+    pub fn spans(&self) -> Vec<Span> {
+        self.scan().into_iter().map(|(_, span)| span).collect()
+    }
+
+    /// Every token of the program, with its position.
+    fn scan(&self) -> Vec<(Token, Span)> {
+        let program = strip_shebang(&self.input);
+        let skipped = self.input.len() - program.len();
+        let mut line = if skipped > 0 { 2 } else { 1 };
+        let mut column = 1;
+        let mut tokens = Vec::new();
+
+        let mut chars = program.char_indices().peekable();
+        while let Some((index, chr)) = chars.next() {
+            let token = self.alphabet.token(chr).or_else(|| match chr {
+                '\r' if self.options.line_endings == LineEndings::Any => match chars.peek() {
+                    Some((_, '\n')) => None,
+                    _ => Some(Token::LineFeed),
+                },
+                '\u{b}' => self.options.vertical_tab.clone(),
                 _ => None,
-            })
-            .collect()
+            });
+            if let Some(token) = token {
+                let offset = skipped + index;
+                tokens.push((
+                    token,
+                    Span {
+                        line,
+                        column,
+                        offset,
+                    },
+                ));
+            }
+
+            // lines are only counted at LF, like the rest of the diagnostics
+            if chr == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+
+        tokens
     }
 }
 
@@ -53,4 +324,106 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn letters() {
+        assert_eq!(letters_to_whitespace("SS STL # push 1\nL"), "   \t\n\n");
+    }
+
+    #[test]
+    fn alphabets() {
+        let alphabet: Alphabet = "space=0,tab=1,lf=2".parse().unwrap();
+        let lexer = Lexer::with_alphabet("0 1x2", alphabet);
+        assert_eq!(lexer.lex(), vec![Token::Space, Token::Tab, Token::LineFeed]);
+        assert_eq!(alphabet.to_whitespace("0 1x2"), " \t\n");
+
+        let alphabet: Alphabet = "tab=T".parse().unwrap();
+        assert_eq!(alphabet.token(' '), Some(Token::Space));
+        assert_eq!(alphabet.token('\t'), None);
+
+        assert!("space=ab".parse::<Alphabet>().is_err());
+        assert!("tab= ".parse::<Alphabet>().is_err());
+        assert!("newline=N".parse::<Alphabet>().is_err());
+    }
+
+    #[test]
+    fn line_endings() {
+        let input = " \r\n\t\r \u{b}";
+        assert_eq!(
+            Lexer::new(input).lex(),
+            vec![Token::Space, Token::LineFeed, Token::Tab, Token::Space]
+        );
+
+        let options = LexerOptions {
+            line_endings: LineEndings::Any,
+            vertical_tab: Some(Token::Tab),
+        };
+        let lexer = Lexer::new(input).options(options);
+        assert_eq!(
+            lexer.lex(),
+            vec![
+                Token::Space,
+                Token::LineFeed,
+                Token::Tab,
+                Token::LineFeed,
+                Token::Space,
+                Token::Tab
+            ]
+        );
+        let offsets: Vec<usize> = lexer.spans().iter().map(|span| span.offset).collect();
+        assert_eq!(offsets, vec![0, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn shebang() {
+        let lexer = Lexer::new("#!/usr/bin/env whitespace run\n\n\n");
+        assert_eq!(lexer.lex(), vec![Token::LineFeed, Token::LineFeed]);
+
+        assert_eq!(Lexer::new("#! \t").lex(), vec![]);
+        assert_eq!(Lexer::new(" #!\t").lex(), vec![Token::Space, Token::Tab]);
+    }
+
+    #[test]
+    fn markdown() {
+        let input =
+            "# doc\n```whitespace\n  \t\n```\nprose \n```rust\n \n```\n```whitespace x\n\n\n";
+
+        assert_eq!(markdown_blocks(input), vec![20..24, 65..67]);
+        assert_eq!(extract_markdown(input), "  \t\n\n\n");
+    }
+
+    #[test]
+    fn marker() {
+        let input = "int main() { return 0; }\n/* %%WS%% */\n \t\n";
+
+        assert_eq!(after_marker(input, "%%WS%%"), Some(38));
+        assert_eq!(&input[38..], " \t\n");
+        assert_eq!(after_marker("  %%WS%%", "%%WS%%"), Some(8));
+        assert_eq!(after_marker(input, "%%ws%%"), None);
+    }
+
+    #[test]
+    fn spans() {
+        let spans = Lexer::new("#!x\na \n\t").spans();
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    line: 2,
+                    column: 2,
+                    offset: 5
+                },
+                Span {
+                    line: 2,
+                    column: 3,
+                    offset: 6
+                },
+                Span {
+                    line: 3,
+                    column: 1,
+                    offset: 7
+                },
+            ]
+        );
+    }
 }
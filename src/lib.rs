@@ -0,0 +1,59 @@
+//! An interpreter for the Whitespace programming language.
+//!
+//! [`lex`] turns source text into tokens and [`parse`] turns it into a
+//! validated [`Program`], which a [`VM`] runs:
+//!
+//! ```
+//! // push 1, push 2, add, end
+//! let program = whitespace::parse::<i32>("   \t\n   \t \n\t   \n\n\n").unwrap();
+//!
+//! let mut vm = whitespace::VM::new();
+//! vm.execute(&program).unwrap();
+//! assert_eq!(vm.stack, vec![3]);
+//! ```
+//!
+//! The modules expose the lower-level pieces, such as parse limits, lexer
+//! options and the VM configuration.
+
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod cell;
+pub mod codegen;
+pub mod diagnostics;
+pub mod error;
+pub mod examples;
+pub mod executor;
+pub mod fuzz;
+pub mod handle;
+pub mod heap;
+pub mod interpreter;
+pub mod lexer;
+pub mod observer;
+pub mod parser;
+pub mod profile;
+pub mod program;
+pub mod stats;
+pub mod testing;
+pub mod validator;
+
+pub use cell::WhitespaceInt;
+pub use error::{Result, WsError};
+pub use interpreter::VM;
+pub use lexer::Token;
+pub use parser::Instruction;
+pub use program::Program;
+
+/// Splits Whitespace source text into tokens, ignoring every other
+/// character.
+pub fn lex(source: &str) -> Vec<Token> {
+    lexer::Lexer::new(source).lex()
+}
+
+/// Parses Whitespace source text and checks that every label it jumps to is
+/// marked exactly once.
+pub fn parse<T: WhitespaceInt>(source: &str) -> Result<Program<T>> {
+    let program = parser::Parser::new(lex(source)).parse()?;
+    program.validate()?;
+
+    Ok(program)
+}
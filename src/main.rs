@@ -1,24 +1,2202 @@
-mod interpreter;
-mod lexer;
-mod parser;
-
 use std::env;
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use tracing_subscriber::EnvFilter;
+
+use whitespace::cell::{DivisionSemantics, OverflowPolicy, WhitespaceInt};
+use whitespace::codegen::Generator;
+use whitespace::diagnostics::{
+    self, Code, Diagnostic, Level, Lint, LintConfig, Severity, Span, Warning,
+};
+use whitespace::examples::{self, EXAMPLES};
+use whitespace::executor::Executor;
+use whitespace::interpreter::{
+    ArgsConvention, CharInput, EofBehavior, Input, Interrupt, IoMode, Output, Prompts, Streams,
+    Terminal, DEFAULT_HEAP_SIZE,
+};
+use whitespace::lexer::{Alphabet, LexerOptions, LineEndings, Token};
+use whitespace::parser::{Instruction, ParseLimits};
+use whitespace::profile::{HeapHeatmap, StackTimeline, Tracer};
+use whitespace::{fuzz, interpreter, lexer, parser, stats, validator, Program, WsError};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Emit {
+    /// Lexed tokens, one per line
+    Tokens,
+    /// Parsed instructions, one per line
+    Ast,
+    /// Parsed program as JSON
+    #[cfg(feature = "serde")]
+    Json,
+    /// Parsed program written back as whitespace, with the shortest labels
+    Whitespace,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum HeatmapFormat {
+    /// One `address,reads,writes` row per accessed address
+    Csv,
+    /// A grid of characters, denser for busier addresses
+    Ascii,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Io {
+    /// Use the terminal
+    Std,
+    /// Discard output and read zeros
+    Null,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Overflow {
+    /// Stop with an error
+    Trap,
+    /// Wrap around in two's complement
+    Wrap,
+    /// Clamp to the smallest or largest value
+    Saturate,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Division {
+    /// Round towards negative infinity, like the reference implementation
+    Floor,
+    /// Round towards zero, like Rust and C
+    Truncate,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CharInputMode {
+    /// Single keypresses on a terminal, plain stdin otherwise
+    Auto,
+    /// Always read single keypresses from the terminal
+    Console,
+    /// Always read characters from stdin
+    Stdin,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Eof {
+    /// Stop with an error
+    Error,
+    /// Store -1
+    MinusOne,
+    /// Store 0
+    Zero,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ArgsLayout {
+    /// NUL-terminated strings in the heap, their addresses and count on the stack
+    Argv,
+    /// The count followed by NUL-terminated strings, all in the heap
+    Heap,
+    /// NUL-terminated strings pushed first character on top, then the count
+    Stack,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Newlines {
+    /// Only LF; CR is ignored, so CR LF is one line feed
+    Lf,
+    /// LF, CR LF and CR on its own
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum TokenKind {
+    Space,
+    Tab,
+    Lf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Encoding {
+    /// The program as is
+    Raw,
+    /// Pairs of hexadecimal digits, one per byte
+    Hex,
+    /// Standard base64
+    Base64,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CellWidth {
+    /// 32-bit signed integers
+    #[value(name = "32")]
+    I32,
+    /// 64-bit signed integers
+    #[value(name = "64")]
+    I64,
+    /// 128-bit signed integers
+    #[value(name = "128")]
+    I128,
+    /// Arbitrary-precision integers
+    #[cfg(feature = "bignum")]
+    #[value(name = "big")]
+    Big,
+    /// The narrowest width that holds every number literal in the program
+    Auto,
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Log more about what the interpreter does; repeat for more detail.
+    /// RUST_LOG takes precedence when set
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Don't log anything
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Report the given lint as a warning
+    #[arg(short = 'W', value_name = "LINT", global = true)]
+    warn: Vec<Lint>,
+
+    /// Silence the given lint
+    #[arg(short = 'A', value_name = "LINT", global = true)]
+    allow: Vec<Lint>,
+
+    /// Treat every warning as an error
+    #[arg(long, global = true)]
+    deny_warnings: bool,
+
+    /// How to print diagnostics
+    #[arg(
+        long,
+        alias = "message-format",
+        value_enum,
+        default_value_t = ErrorFormat::Human,
+        global = true
+    )]
+    error_format: ErrorFormat,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run a program
+    Run(Box<RunArgs>),
+    /// Continue a program from the state saved by `run --checkpoint`
+    #[cfg(feature = "serde")]
+    Resume(Box<ResumeArgs>),
+    /// Run every `.ws` program in a directory and summarize the results
+    RunAll(RunAllArgs),
+    /// Run random programs and report those that make the interpreter panic,
+    /// hang or break one of its guarantees
+    Fuzz(FuzzArgs),
+    /// Parse, validate and lint a program without running it
+    Check(ProgramArgs),
+    /// Print the instructions of a program, one per line
+    Disasm(ProgramArgs),
+    /// Count the instructions, labels and literals of a program without
+    /// running it
+    Stats(StatsArgs),
+    /// Print a program with its whitespace drawn as colored S, T and L, one
+    /// instruction per line
+    Visualize(VisualizeArgs),
+    /// Print a SHA-256 hash of the tokens of a program, which ignores its
+    /// comments
+    Hash(ProgramArgs),
+    /// Translate a program written with the letters S, T and L into Whitespace
+    Asm {
+        /// Program where S, T and L stand for space, tab and line feed; every
+        /// other character is a comment. Read from stdin if `-` or omitted
+        file: Option<PathBuf>,
+    },
+    /// Print a program without its comments
+    Fmt {
+        /// Whitespace program to format, read from stdin if `-` or omitted
+        file: Option<PathBuf>,
+    },
+    /// List, show and run the example programs that ship with the interpreter
+    Examples {
+        #[command(subcommand)]
+        command: ExamplesCommand,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print the man page, or write one page per command to a directory
+    Man {
+        /// Directory to write `whitespace.1`, `whitespace-run.1`, ... to
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ExamplesCommand {
+    /// List the examples
+    List,
+    /// Print the source of an example, written with letters
+    Show { name: String },
+    /// Run an example
+    Run {
+        name: String,
+
+        /// Options for `whitespace run`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<OsString>,
+    },
+}
+
+/// `whitespace run` options given to `whitespace examples run`.
+#[derive(Debug, Parser)]
+#[command(name = "whitespace examples run")]
+struct ExampleRunArgs {
+    #[command(flatten)]
+    args: RunArgs,
+}
+
+/// Options for commands that parse a program.
+#[derive(Debug, Args)]
+struct ProgramArgs {
+    /// Whitespace program, read from stdin if `-` or omitted
+    file: Option<PathBuf>,
+
+    /// Use this program text instead of reading a file
+    #[arg(
+        short = 'e',
+        long = "eval",
+        value_name = "PROGRAM",
+        conflicts_with = "file"
+    )]
+    eval: Option<String>,
+
+    /// Read the program with the letters S, T and L standing for space, tab
+    /// and line feed, ignoring everything else
+    #[arg(long)]
+    letters: bool,
+
+    /// Read the program with other characters standing for space, tab and
+    /// line feed, as in `space=S,tab=T,lf=L`
+    #[arg(long, value_name = "MAPPING", conflicts_with = "letters")]
+    map: Option<Alphabet>,
+
+    /// Only read the contents of the ```whitespace code blocks of a Markdown
+    /// document; implied for `.md` files
+    #[arg(long)]
+    markdown: bool,
+
+    /// Ignore everything up to and including the first line containing
+    /// MARKER, for polyglot files that are also valid in another language
+    #[arg(long, value_name = "MARKER")]
+    start_marker: Option<String>,
+
+    /// What counts as a line feed
+    #[arg(long, value_enum, default_value_t = Newlines::Lf)]
+    line_endings: Newlines,
+
+    /// Read vertical tabs as this token instead of ignoring them
+    #[arg(long, value_enum, value_name = "TOKEN")]
+    vertical_tab: Option<TokenKind>,
+
+    /// How the program text is encoded
+    #[arg(long, value_enum, default_value_t = Encoding::Raw)]
+    encoding: Encoding,
+
+    /// Reject programs with more than N instructions
+    #[arg(long, value_name = "N")]
+    max_instructions: Option<usize>,
+
+    /// Reject labels longer than N tokens
+    #[arg(long, value_name = "N")]
+    max_label_length: Option<usize>,
+
+    /// Reject number literals with more than N bits
+    #[arg(long, value_name = "N")]
+    max_number_bits: Option<usize>,
+
+    /// Integer type used for stack and heap cells
+    #[arg(long, value_enum, default_value_t = CellWidth::I32)]
+    cell_width: CellWidth,
+}
+
+#[derive(Debug, Args)]
+struct StatsArgs {
+    #[command(flatten)]
+    program: ProgramArgs,
+
+    /// Report what takes the most room instead: the bytes of each labelled
+    /// section and of the largest instructions
+    #[arg(long)]
+    size: bool,
+}
+
+#[derive(Debug, Args)]
+struct VisualizeArgs {
+    #[command(flatten)]
+    program: ProgramArgs,
+
+    /// Write an HTML page instead
+    #[arg(long)]
+    html: bool,
+}
+
+#[derive(Debug, Args)]
+struct RunAllArgs {
+    /// Directory of programs
+    dir: PathBuf,
+
+    /// Feed each program the file named after it with an `.in` extension
+    /// from this directory, if there is one; programs read no input otherwise
+    #[arg(long, value_name = "DIR")]
+    input_dir: Option<PathBuf>,
+
+    /// Stop each program after this many seconds
+    #[arg(long, value_name = "SECS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// Integer type used for stack and heap cells
+    #[arg(long, value_enum, default_value_t = CellWidth::I32)]
+    cell_width: CellWidth,
+}
+
+#[derive(Debug, Args)]
+struct FuzzArgs {
+    /// Number of programs to run
+    #[arg(long, default_value_t = 10_000)]
+    runs: u64,
+
+    /// Seed of the first program, the next ones counting up from it; picked
+    /// from the clock if omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Run only the program of this seed, printing it and how it stopped
+    #[arg(long, value_name = "SEED", conflicts_with_all = ["runs", "seed"])]
+    replay: Option<u64>,
+
+    /// Random instructions in each program
+    #[arg(long, default_value_t = 32)]
+    len: usize,
+
+    /// Steps each program may take
+    #[arg(long, default_value_t = 10_000)]
+    fuel: u64,
+
+    /// Seconds after which a program that is still running counts as hung
+    #[arg(long, value_name = "SECS", value_parser = parse_timeout, default_value = "1")]
+    timeout: Duration,
+}
+
+#[derive(Debug, Args)]
+struct RunArgs {
+    #[command(flatten)]
+    program: ProgramArgs,
+
+    /// Print an intermediate representation instead of running the program
+    #[arg(long, value_enum)]
+    emit: Option<Emit>,
+
+    /// Where program input and output go
+    #[arg(long, value_enum, default_value_t = Io::Std)]
+    io: Io,
+
+    /// Run the program again, on a cleared screen, every time the file changes
+    #[arg(long)]
+    watch: bool,
+
+    /// Read the program's input from this file instead of stdin; needed to
+    /// give input to a program that is itself read from stdin
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+
+    /// Write the program's output to this file instead of stdout
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Write per-address heap read/write counts to this file
+    #[arg(long, value_name = "FILE")]
+    heap_heatmap: Option<PathBuf>,
+
+    /// How --heap-heatmap is written
+    #[arg(long, value_enum, default_value_t = HeatmapFormat::Csv, requires = "heap_heatmap")]
+    heatmap_format: HeatmapFormat,
+
+    /// Write the stack depth over time to this CSV file
+    #[arg(long, value_name = "FILE")]
+    stack_timeline: Option<PathBuf>,
+
+    /// Sample the stack depth every N instructions
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    stack_timeline_interval: u64,
+
+    /// Print every executed instruction with the top of the stack, to stderr
+    /// or to the given file
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+    trace: Option<Option<PathBuf>>,
+
+    /// Wait this many milliseconds before each instruction
+    #[arg(long, value_name = "MS")]
+    delay: Option<u64>,
+
+    /// With --delay, keep the instruction pointer and stack displayed on stderr
+    #[arg(long, requires = "delay")]
+    show_stack: bool,
+
+    /// Stop tracing after N instructions
+    #[arg(long, value_name = "N", requires = "trace")]
+    trace_limit: Option<u64>,
+
+    /// Fail with a diff if the program output differs from this file
+    #[arg(long, value_name = "FILE")]
+    expect_output: Option<PathBuf>,
+
+    /// Prompt shown before reading a number from a terminal
+    #[arg(long, value_name = "TEXT", default_value = "number> ")]
+    number_prompt: String,
+
+    /// Prompt shown before reading a character from a terminal
+    #[arg(long, value_name = "TEXT", default_value = "char> ")]
+    char_prompt: String,
+
+    /// Never show input prompts
+    #[arg(long)]
+    no_prompt: bool,
+
+    /// Report a closed stdout (broken pipe) as an error instead of exiting quietly
+    #[arg(long)]
+    strict_io: bool,
+
+    /// Arguments passed to the program, laid out as --args-convention says
+    #[arg(long, num_args = 0.., allow_hyphen_values = true, value_name = "ARG")]
+    args: Option<Vec<String>>,
+
+    /// How --args are handed to the program
+    #[arg(long, value_enum, default_value_t = ArgsLayout::Argv)]
+    args_convention: ArgsLayout,
+
+    /// Heap address where the argv and heap conventions store arguments
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    args_at: usize,
+
+    /// Limit the heap to addresses below N; it grows on demand up to that
+    #[arg(long, value_name = "N")]
+    heap_size: Option<usize>,
+
+    /// Load the bytes of this file into the heap, one per cell
+    #[arg(long, value_name = "FILE")]
+    heap_init: Option<PathBuf>,
+
+    /// Load heap cells from this JSON file: an array of numbers, or an object
+    /// mapping addresses to numbers
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "FILE")]
+    heap_init_json: Option<PathBuf>,
+
+    /// Address where --heap-init and JSON arrays start
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    heap_init_at: usize,
+
+    /// Fail when the program reads a heap address it never stored to
+    #[arg(long)]
+    trap_uninit_heap: bool,
+
+    /// Fail when the stack would hold more than N items
+    #[arg(long, value_name = "N")]
+    max_stack: Option<usize>,
+
+    /// Fail when subroutine calls nest deeper than N
+    #[arg(long, value_name = "N")]
+    max_call_depth: Option<usize>,
+
+    /// How ReadChar reads a character
+    #[arg(long, value_enum, default_value_t = CharInputMode::Auto)]
+    char_input: CharInputMode,
+
+    /// What ReadChar and ReadNumber do once the input is exhausted
+    #[arg(long, value_enum, default_value_t = Eof::Error)]
+    eof: Eof,
+
+    /// Use the number on top of the stack at EndProgram as the exit code
+    #[arg(long)]
+    exit_code_from_stack: bool,
+
+    /// End the program cleanly when it runs past its last instruction
+    #[arg(long)]
+    allow_implicit_exit: bool,
+
+    /// Stop the program after this many seconds
+    #[arg(long, value_name = "SECS", value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+
+    /// Stop the program after it runs this many instructions
+    #[arg(long, value_name = "STEPS")]
+    fuel: Option<u64>,
+
+    /// Save the VM state to this file if the program is interrupted or runs
+    /// out of fuel, then exit with status 130
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+
+    /// State saved with --checkpoint to continue from, set by `resume`
+    #[cfg(feature = "serde")]
+    #[arg(skip)]
+    resume: Option<PathBuf>,
+
+    /// What Add, Substract and Multiply do when the result doesn't fit in a cell
+    #[arg(long, value_enum, default_value_t = Overflow::Trap)]
+    overflow: Overflow,
+
+    /// How Divide and Modulo round when an operand is negative
+    #[arg(long, value_enum, default_value_t = Division::Floor)]
+    division: Division,
+}
+
+#[derive(Debug, Args)]
+#[cfg(feature = "serde")]
+struct ResumeArgs {
+    /// State file written by `run --checkpoint`
+    #[arg(value_name = "STATE")]
+    state: PathBuf,
+
+    /// The program that was interrupted, and options to run the rest with
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+/// What `run --checkpoint` saves: the VM, and a hash of the program it was
+/// running so that `resume` doesn't continue it with another one.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Checkpoint<V> {
+    program: String,
+    vm: V,
+}
+
+fn parse_timeout(secs: &str) -> Result<Duration, String> {
+    let secs: f64 = secs.parse().map_err(|error| format!("{error}"))?;
+
+    Duration::try_from_secs_f64(secs).map_err(|error| format!("{error}"))
+}
+
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => "off",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        (false, _) => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .without_time()
+        .init();
+}
+
+fn report(diagnostic: &Diagnostic, format: ErrorFormat) {
+    match format {
+        ErrorFormat::Human => eprintln!("{diagnostic}"),
+        ErrorFormat::Json => eprintln!("{}", diagnostic.to_json()),
+    }
+}
 
 fn main() {
-    let file = env::args().nth(1).unwrap();
-    let content = fs::read_to_string(file).unwrap();
+    // `whitespace prog.ws` is short for `whitespace run prog.ws`, so scripts
+    // can start with `#!/usr/bin/env whitespace`
+    let mut args: Vec<OsString> = env::args_os().collect();
+    if let Some(arg) = args.get(1) {
+        let is_command = Cli::command().find_subcommand(arg).is_some();
+        let is_program = Path::new(arg).is_file() || arg.to_str().is_some_and(is_url);
+        if !is_command && is_program {
+            args.insert(1, "run".into());
+        }
+    }
+
+    let cli = Cli::parse_from(&args);
+    let format = cli.error_format;
+    init_logging(cli.verbose, cli.quiet);
+
+    let mut lints = LintConfig::default();
+    for lint in &cli.allow {
+        lints.set(*lint, Level::Allow);
+    }
+    for lint in &cli.warn {
+        lints.set(*lint, Level::Warn);
+    }
+    lints.deny_warnings(cli.deny_warnings);
+
+    match cli.command {
+        Command::Run(run_args) if run_args.watch => {
+            match (&run_args.program.file, &run_args.program.eval) {
+                (Some(path), None)
+                    if path != Path::new("-") && !path.to_str().is_some_and(is_url) =>
+                {
+                    watch(path, args, format)
+                }
+                _ => {
+                    let message = "--watch needs the program to be a file";
+                    report(&Diagnostic::error(Code::IoError, message), format);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Run(args) => run_program(*args, lints, format),
+        #[cfg(feature = "serde")]
+        Command::Resume(args) => {
+            let ResumeArgs { state, mut run } = *args;
+            run.resume = Some(state);
+            run_program(run, lints, format);
+        }
+        Command::Check(program) => {
+            let source = Source::load(&program, format);
+            let tokens = source.tokens();
+
+            let cell_width = program.cell_width;
+            let command = CheckCommand {
+                program,
+                lints,
+                source,
+                format,
+            };
+            with_cell_width(cell_width, tokens, command);
+        }
+        Command::Disasm(program) => {
+            let source = Source::load(&program, format);
+            let tokens = source.tokens();
+
+            let cell_width = program.cell_width;
+            let command = DisasmCommand {
+                program,
+                source,
+                format,
+            };
+            with_cell_width(cell_width, tokens, command);
+        }
+        Command::Stats(args) => {
+            let source = Source::load(&args.program, format);
+            let tokens = source.tokens();
+
+            let cell_width = args.program.cell_width;
+            let command = StatsCommand {
+                args,
+                source,
+                format,
+            };
+            with_cell_width(cell_width, tokens, command);
+        }
+        Command::Visualize(args) => {
+            let source = Source::load(&args.program, format);
+            let tokens = source.tokens();
+
+            let cell_width = args.program.cell_width;
+            let command = VisualizeCommand {
+                args,
+                source,
+                format,
+            };
+            with_cell_width(cell_width, tokens, command);
+        }
+        Command::Hash(program) => {
+            let source = Source::load(&program, format);
+            let hex = program_hash(&source.tokens());
+            let name = match (&program.eval, &program.file) {
+                (None, Some(path)) => path.display().to_string(),
+                _ => "-".to_string(),
+            };
+            println!("{hex}  {name}");
+        }
+        Command::RunAll(args) => run_all(&args, format),
+        Command::Fuzz(args) => fuzz(&args),
+        Command::Asm { file } => {
+            let content = read_program(file.as_deref(), format);
+            let tokens = lexer::Lexer::with_alphabet(content.as_str(), Alphabet::LETTERS).lex();
+            // programs that don't parse, or not with 64-bit literals, are
+            // still translated, just not linted
+            if let Ok(program) = parser::Parser::<i64>::new(tokens).parse() {
+                report_warnings(diagnostics::check_program(&program), &lints, format);
+            }
+            print!("{}", lexer::letters_to_whitespace(&content));
+        }
+        Command::Fmt { file } => {
+            let content = read_program(file.as_deref(), format);
+            let tokens = lexer::Lexer::new(content.as_str()).lex();
+            if let Some(shebang) = content.lines().next().filter(|line| line.starts_with("#!")) {
+                println!("{shebang}");
+            }
+            print!("{}", tokens.iter().map(Token::as_char).collect::<String>());
+        }
+        Command::Examples { command } => examples(command, lints, format),
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "whitespace", &mut io::stdout());
+        }
+        Command::Man { out_dir } => {
+            let written = match &out_dir {
+                Some(dir) => clap_mangen::generate_to(Cli::command(), dir),
+                None => clap_mangen::Man::new(Cli::command()).render(&mut io::stdout()),
+            };
+            let path = out_dir.as_deref().unwrap_or(Path::new("stdout"));
+            write_or_exit(path, written, format);
+        }
+    }
+}
+
+/// Runs the program in a child process every time `path` is modified, until
+/// interrupted. `args` are the command line arguments of this process.
+fn watch(path: &Path, mut args: Vec<OsString>, format: ErrorFormat) -> ! {
+    args.retain(|arg| arg != "--watch");
+    let exe = env::current_exe().unwrap_or_else(|error| {
+        let message = format!("cannot find the interpreter executable: {error}");
+        report(&Diagnostic::error(Code::IoError, message), format);
+        process::exit(1);
+    });
+
+    let term = console::Term::stdout();
+    let mut last_modified = None;
+    loop {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+        if let Ok(modified) = modified {
+            if last_modified != Some(modified) {
+                last_modified = Some(modified);
+                if term.is_term() {
+                    let _ = term.clear_screen();
+                }
 
-    let lexer = lexer::Lexer::new(content);
-    let tokens = lexer.lex();
+                match process::Command::new(&exe).args(&args[1..]).status() {
+                    Ok(status) => eprintln!("\n[{status}, waiting for changes]"),
+                    Err(error) => {
+                        let message = format!("cannot run {}: {error}", exe.display());
+                        report(&Diagnostic::error(Code::IoError, message), format);
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// Hex SHA-256 of the tokens of a program, so that comments don't change it.
+fn program_hash(tokens: &[Token]) -> String {
+    let tokens: String = tokens.iter().map(Token::as_char).collect();
+
+    let hash = Sha256::digest(tokens.as_bytes());
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn run_program(args: RunArgs, lints: LintConfig, format: ErrorFormat) {
+    let source = Source::load(&args.program, format);
+    let tokens = source.tokens();
+
+    if let Some(Emit::Tokens) = args.emit {
+        for token in &tokens {
+            println!("{token:?}");
+        }
+        return;
+    }
+
+    let cell_width = args.program.cell_width;
+    let command = RunCommand {
+        args,
+        lints,
+        source,
+        format,
+    };
+    with_cell_width(cell_width, tokens, command);
+}
+
+fn examples(command: ExamplesCommand, lints: LintConfig, format: ErrorFormat) {
+    let find = |name: &str| {
+        examples::find(name).unwrap_or_else(|| {
+            let message = format!("no example named `{name}`; see `whitespace examples list`");
+            report(&Diagnostic::error(Code::IoError, message), format);
+            process::exit(1);
+        })
+    };
+
+    match command {
+        ExamplesCommand::List => {
+            let width = EXAMPLES.iter().map(|example| example.name.len()).max();
+            for example in &EXAMPLES {
+                println!(
+                    "{:width$}  {}",
+                    example.name,
+                    example.description,
+                    width = width.unwrap_or(0)
+                );
+            }
+        }
+        ExamplesCommand::Show { name } => print!("{}", find(&name).source.trim_start()),
+        ExamplesCommand::Run { name, args } => {
+            let example = find(&name);
+            let program = ["--letters", "-e", example.source];
+            let run = ExampleRunArgs::parse_from(
+                ["whitespace examples run"]
+                    .iter()
+                    .chain(&program)
+                    .chain(example.args)
+                    .map(OsString::from)
+                    .chain(args),
+            );
+            run_program(run.args, lints, format);
+        }
+    }
+}
+
+/// The program given on the command line, and the whitespace it stands for.
+struct Source {
+    /// The program text as given, possibly written with letters.
+    text: String,
+    /// The whitespace program, after any translation from letters.
+    content: String,
+    /// Position in `text` of each token of `content`.
+    spans: Vec<Span>,
+    /// Characters `text` is written with.
+    alphabet: Alphabet,
+    /// How `text` was lexed.
+    options: LexerOptions,
+    /// Parts of `text` holding the program, outside of Markdown prose.
+    regions: Vec<Range<usize>>,
+}
+
+impl Source {
+    fn load(program: &ProgramArgs, format: ErrorFormat) -> Self {
+        let text = match &program.eval {
+            Some(text) => text.clone(),
+            None => read_program(program.file.as_deref(), format),
+        };
+        let text = match program.encoding {
+            Encoding::Raw => text,
+            encoding => decode_text(&text, encoding)
+                .and_then(|bytes| Ok(decode_program(bytes, false)?))
+                .unwrap_or_else(|error| {
+                    let message = format!("cannot decode the program: {error:#}");
+                    report(&Diagnostic::error(Code::IoError, message), format);
+                    process::exit(1);
+                }),
+        };
+        let markdown = program.markdown
+            || (program.eval.is_none()
+                && program
+                    .file
+                    .as_deref()
+                    .and_then(Path::extension)
+                    .is_some_and(|extension| extension == "md"));
+        let start = match &program.start_marker {
+            Some(marker) => lexer::after_marker(&text, marker).unwrap_or_else(|| {
+                let message = format!("the program has no line containing {marker:?}");
+                report(&Diagnostic::error(Code::IoError, message), format);
+                process::exit(1);
+            }),
+            None => 0,
+        };
+        let regions: Vec<Range<usize>> = match markdown {
+            true => lexer::markdown_blocks(&text[start..])
+                .into_iter()
+                .map(|block| block.start + start..block.end + start)
+                .collect(),
+            false => std::iter::once(start..text.len()).collect(),
+        };
+        let code: String = regions.iter().map(|region| &text[region.clone()]).collect();
+        let alphabet = match program.letters {
+            true => Alphabet::LETTERS,
+            false => program.map.unwrap_or_default(),
+        };
+        let options = LexerOptions {
+            line_endings: match program.line_endings {
+                Newlines::Lf => LineEndings::Lf,
+                Newlines::Any => LineEndings::Any,
+            },
+            vertical_tab: program.vertical_tab.map(|token| match token {
+                TokenKind::Space => Token::Space,
+                TokenKind::Tab => Token::Tab,
+                TokenKind::Lf => Token::LineFeed,
+            }),
+        };
+        let content = match (alphabet, &options) {
+            (Alphabet::WHITESPACE, options) if *options == LexerOptions::default() => code,
+            (alphabet, options) => lexer::Lexer::with_alphabet(code, alphabet)
+                .options(options.clone())
+                .lex()
+                .iter()
+                .map(Token::as_char)
+                .collect(),
+        };
+        let mut spans = lexer::Lexer::with_alphabet(text.as_str(), alphabet)
+            .options(options.clone())
+            .spans();
+        spans.retain(|span| regions.iter().any(|region| region.contains(&span.offset)));
+
+        Self {
+            text,
+            content,
+            spans,
+            alphabet,
+            options,
+            regions,
+        }
+    }
+
+    /// Source lints, on the text as written rather than the translated
+    /// content, which has lost its comments. Letters programs are skipped, as
+    /// whitespace in them is meant to be ignored.
+    fn check(&self) -> Vec<Warning> {
+        if self.alphabet != Alphabet::WHITESPACE {
+            return Vec::new();
+        }
+
+        let mut warnings = diagnostics::check_source(&self.text, &self.options);
+        warnings.retain(|warning| {
+            warning.span.as_ref().is_none_or(|span| {
+                self.regions
+                    .iter()
+                    .any(|region| region.contains(&span.offset))
+            })
+        });
+        warnings
+    }
+
+    fn tokens(&self) -> Vec<Token> {
+        lexer::Lexer::new(self.content.as_str()).lex()
+    }
+
+    /// Position of the token at `index`; errors at the end of the program
+    /// point at its last token.
+    fn span_of(&self, index: usize) -> Option<Span> {
+        self.spans.get(index).or(self.spans.last()).copied()
+    }
+
+    /// The source line around `span`, with its whitespace made visible.
+    fn excerpt(&self, span: Span) -> String {
+        let color = io::stderr().is_terminal();
+        let visualize = self.alphabet == Alphabet::WHITESPACE;
+        diagnostics::excerpt(&self.text, span, visualize, color)
+    }
+
+    /// Reports `diagnostic`, followed in human output by the source it
+    /// points at.
+    fn report(&self, diagnostic: &Diagnostic, format: ErrorFormat) {
+        report(diagnostic, format);
+        if let (ErrorFormat::Human, Some(span)) = (format, diagnostic.span) {
+            eprintln!("{}", self.excerpt(span));
+        }
+    }
+}
+
+/// Reads the program text from `file`, or from stdin for `-` or no file,
+/// decompressing it if it is gzipped.
+fn read_program(file: Option<&Path>, format: ErrorFormat) -> String {
+    match file {
+        Some(path) if path != Path::new("-") => {
+            let gzipped = path.extension().is_some_and(|extension| extension == "gz");
+            read_bytes(path)
+                .and_then(|bytes| Ok(decode_program(bytes, gzipped)?))
+                .unwrap_or_else(|error| {
+                    let message = format!("cannot read {}: {error:#}", path.display());
+                    report(&Diagnostic::error(Code::IoError, message), format);
+                    process::exit(1);
+                })
+        }
+        _ => {
+            if file.is_none() && io::stdin().is_terminal() {
+                let message = "no program given; pass a file, or `-` to read it from stdin";
+                report(&Diagnostic::error(Code::IoError, message), format);
+                process::exit(1);
+            }
+
+            let mut bytes = Vec::new();
+            let read = io::stdin().read_to_end(&mut bytes);
+            read.and_then(|_| decode_program(bytes, false))
+                .unwrap_or_else(|error| {
+                    let message = format!("cannot read the program from stdin: {error}");
+                    report(&Diagnostic::error(Code::IoError, message), format);
+                    process::exit(1);
+                })
+        }
+    }
+}
+
+/// Reads a program file, or downloads it if `path` is an HTTP(S) URL.
+fn read_bytes(path: &Path) -> anyhow::Result<Vec<u8>> {
+    match path.to_str().filter(|path| is_url(path)) {
+        #[cfg(feature = "http")]
+        Some(url) => fetch(url),
+        #[cfg(not(feature = "http"))]
+        Some(_) => anyhow::bail!("fetching programs over HTTP needs the `http` feature"),
+        None => Ok(fs::read(path)?),
+    }
+}
+
+fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Largest program [`fetch`] downloads.
+#[cfg(feature = "http")]
+const MAX_DOWNLOAD_SIZE: u64 = 16 * 1024 * 1024;
+
+/// How long [`fetch`] waits for the whole download.
+#[cfg(feature = "http")]
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[cfg(feature = "http")]
+fn fetch(url: &str) -> anyhow::Result<Vec<u8>> {
+    let agent = ureq::AgentBuilder::new().timeout(DOWNLOAD_TIMEOUT).build();
+    // ureq errors start with the URL, which the caller already reports
+    let response = agent.get(url).call().map_err(|error| match error {
+        ureq::Error::Status(code, response) => {
+            anyhow::anyhow!(
+                "the server responded with {code} {}",
+                response.status_text()
+            )
+        }
+        ureq::Error::Transport(transport) => {
+            let mut message = transport.kind().to_string();
+            if let Some(detail) = transport.message() {
+                message = format!("{message}: {detail}");
+            }
+            if let Some(source) = std::error::Error::source(&transport) {
+                message = format!("{message}: {source}");
+            }
+            anyhow::anyhow!(message)
+        }
+    })?;
+
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_DOWNLOAD_SIZE + 1)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_DOWNLOAD_SIZE {
+        anyhow::bail!("the program is larger than {MAX_DOWNLOAD_SIZE} bytes");
+    }
+
+    Ok(bytes)
+}
 
-    let mut parser = parser::Parser::new(tokens);
-    parser.parse().unwrap();
+/// Decodes a program sent as hex or base64 text, ignoring the whitespace that
+/// was inserted or mangled along the way.
+fn decode_text(text: &str, encoding: Encoding) -> anyhow::Result<Vec<u8>> {
+    let encoded: String = text.chars().filter(|chr| !chr.is_whitespace()).collect();
+
+    match encoding {
+        Encoding::Raw => Ok(text.as_bytes().to_vec()),
+        Encoding::Hex => {
+            let digit = |chr: char| {
+                chr.to_digit(16)
+                    .ok_or_else(|| anyhow::anyhow!("{chr:?} is not a hexadecimal digit"))
+            };
+            let digits: Vec<char> = encoded.chars().collect();
+            if !digits.len().is_multiple_of(2) {
+                anyhow::bail!("odd number of hexadecimal digits");
+            }
+
+            digits
+                .chunks(2)
+                .map(|pair| Ok((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+                .collect()
+        }
+        Encoding::Base64 => Ok(base64::engine::general_purpose::STANDARD.decode(encoded)?),
+    }
+}
+
+/// Turns the bytes of a program into text, decompressing them first if they
+/// are `gzipped` or start with the gzip magic bytes.
+fn decode_program(bytes: Vec<u8>, gzipped: bool) -> io::Result<String> {
+    let bytes = match gzipped || bytes.starts_with(&[0x1f, 0x8b]) {
+        true => {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+            decompressed
+        }
+        false => bytes,
+    };
+
+    String::from_utf8(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+fn read_source(path: &Path, format: ErrorFormat) -> String {
+    fs::read_to_string(path).unwrap_or_else(|error| {
+        let message = format!("cannot read {}: {error}", path.display());
+        report(&Diagnostic::error(Code::IoError, message), format);
+        process::exit(1);
+    })
+}
+
+/// A command that parses the program, and so needs to know the cell type.
+trait CellCommand {
+    type Output;
+
+    fn run<T: WhitespaceInt>(self, tokens: Vec<Token>) -> Self::Output;
+}
+
+fn with_cell_width<C: CellCommand>(
+    cell_width: CellWidth,
+    tokens: Vec<Token>,
+    command: C,
+) -> C::Output {
+    match cell_width {
+        CellWidth::I32 => command.run::<i32>(tokens),
+        CellWidth::Auto if literals_fit::<i32>(&tokens) => command.run::<i32>(tokens),
+        CellWidth::I64 => command.run::<i64>(tokens),
+        CellWidth::Auto if literals_fit::<i64>(&tokens) => command.run::<i64>(tokens),
+        CellWidth::I128 => command.run::<i128>(tokens),
+        #[cfg(not(feature = "bignum"))]
+        CellWidth::Auto => command.run::<i128>(tokens),
+        #[cfg(feature = "bignum")]
+        CellWidth::Auto if literals_fit::<i128>(&tokens) => command.run::<i128>(tokens),
+        #[cfg(feature = "bignum")]
+        CellWidth::Big | CellWidth::Auto => command.run::<num_bigint::BigInt>(tokens),
+    }
+}
+
+/// Whether every number literal in the program fits in `T`.
+fn literals_fit<T: WhitespaceInt>(tokens: &[Token]) -> bool {
+    let result = parser::Parser::<T>::new(tokens.to_vec()).parse();
+
+    !result.is_err_and(|error| matches!(error, WsError::LiteralOverflow(_)))
+}
+
+fn parse_limits(program: &ProgramArgs) -> ParseLimits {
+    ParseLimits {
+        max_instructions: program.max_instructions,
+        max_label_length: program.max_label_length,
+        max_number_bits: program.max_number_bits,
+    }
+}
+
+/// Parses and validates the program, reporting every error with the source
+/// it points at and exiting if there were any.
+fn parse_program<T: WhitespaceInt>(
+    program: &ProgramArgs,
+    source: &Source,
+    tokens: Vec<Token>,
+    format: ErrorFormat,
+) -> Program<T> {
+    let parser = parser::Parser::<T>::with_limits(tokens, parse_limits(program));
+    let (parsed, problems) = parser.parse_all();
+    let mut errors = Vec::new();
+    let mut complete = true;
+    for problem in problems {
+        let code = match matches!(problem.error, WsError::LiteralOverflow(_)) {
+            true => Code::LiteralOverflow,
+            false => {
+                complete = false;
+                Code::ParseError
+            }
+        };
+        let mut diagnostic = Diagnostic::error(code, format!("{:#}", problem.error));
+        diagnostic.span = source.span_of(problem.token);
+        errors.push(diagnostic);
+    }
+
+    for problem in validator::problems(&parsed) {
+        // the rest of a program that failed to parse may mark the label
+        if problem.code == Code::UndefinedLabel && !complete {
+            continue;
+        }
+
+        let mut diagnostic = Diagnostic::error(problem.code, problem.message)
+            .with_instruction(problem.instruction)
+            .with_label(problem.label);
+        diagnostic.span = source.span_of(parsed.starts()[problem.instruction]);
+        errors.push(diagnostic);
+    }
+
+    errors.sort_by_key(|error| error.span);
+    for error in &errors {
+        source.report(error, format);
+    }
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+
+    parsed
+}
+
+/// Reports source and program lint warnings, exiting if any of them is denied.
+fn lint<T>(
+    instructions: &[Instruction<T>],
+    source: &Source,
+    lints: &LintConfig,
+    format: ErrorFormat,
+) {
+    let mut warnings = source.check();
+    warnings.extend(diagnostics::check_program(instructions));
+    report_warnings(warnings, lints, format);
+}
+
+fn report_warnings(warnings: Vec<Warning>, lints: &LintConfig, format: ErrorFormat) {
+    let mut denied = false;
+    for warning in warnings {
+        let severity = match lints.level(warning.lint) {
+            Level::Allow => continue,
+            Level::Warn => Severity::Warning,
+            Level::Deny => {
+                denied = true;
+                Severity::Error
+            }
+        };
+        report(&Diagnostic::from_warning(warning, severity), format);
+    }
+    if denied {
+        process::exit(1);
+    }
+}
+
+/// How one program of `run-all` went.
+struct BatchOutcome {
+    result: anyhow::Result<()>,
+    elapsed: Duration,
+    steps: u64,
+}
+
+/// Parses and runs a program without any output, reading from `input`.
+struct BatchCommand {
+    input: Input,
+    timeout: Option<Duration>,
+}
+
+impl CellCommand for BatchCommand {
+    type Output = BatchOutcome;
+
+    fn run<T: WhitespaceInt>(self, tokens: Vec<Token>) -> BatchOutcome {
+        let start = Instant::now();
+        let mut vm = interpreter::VM::<T>::new();
+        vm.host_io = Box::new(Streams {
+            reader: self.input,
+            writer: io::sink(),
+        });
+        vm.interrupt = self.timeout.map(Interrupt::after);
+
+        let result = parser::Parser::<T>::new(tokens)
+            .parse()
+            .and_then(|program| program.validate().map(|()| program))
+            .and_then(|program| vm.execute(&program))
+            .map_err(anyhow::Error::from)
+            .map_err(|error| match (self.timeout, is_interrupted(&error)) {
+                (Some(timeout), true) => error.context(format!("timed out after {timeout:?}")),
+                _ => error,
+            });
+
+        BatchOutcome {
+            result,
+            elapsed: start.elapsed(),
+            steps: vm.steps,
+        }
+    }
+}
+
+fn fuzz(args: &FuzzArgs) {
+    let options = fuzz::Options {
+        fuel: args.fuel,
+        timeout: args.timeout,
+        ..fuzz::Options::default()
+    };
+
+    if let Some(seed) = args.replay {
+        let case = fuzz::Case::generate(seed, args.len);
+        for (i, instruction) in case.program.instructions().iter().enumerate() {
+            println!("{i}: {instruction}");
+        }
+        println!("input: {:?}", case.input);
+
+        match fuzz::check(&case, &options) {
+            Ok(fuzz::Outcome { error: None, steps }) => println!("ended after {steps} steps"),
+            Ok(fuzz::Outcome {
+                error: Some(error),
+                steps,
+            }) => println!("stopped after {steps} steps: {error}"),
+            Err(failure) => {
+                println!("{failure}");
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let first = args.seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    });
+    println!("running {} programs from seed {first}", args.runs);
+
+    // failures are reported with their seed, so the default hook's message
+    // for every panicking program is only noise
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let mut failures = 0;
+    for seed in (0..args.runs).map(|run| first.wrapping_add(run)) {
+        let case = fuzz::Case::generate(seed, args.len);
+        if let Err(failure) = fuzz::check(&case, &options) {
+            failures += 1;
+            println!("seed {seed}: {failure}");
+        }
+    }
+
+    std::panic::set_hook(hook);
+
+    println!();
+    println!("{} passed, {failures} failed", args.runs - failures);
+    if failures > 0 {
+        println!("replay one with `whitespace fuzz --replay SEED`");
+        process::exit(1);
+    }
+}
+
+fn run_all(args: &RunAllArgs, format: ErrorFormat) {
+    let entries = fs::read_dir(&args.dir).unwrap_or_else(|error| {
+        let message = format!("cannot read {}: {error}", args.dir.display());
+        report(&Diagnostic::error(Code::IoError, message), format);
+        process::exit(1);
+    });
+    let mut programs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ws"))
+        .collect();
+    programs.sort();
+
+    let width = programs
+        .iter()
+        .filter_map(|path| path.file_name())
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max("program".len());
+    println!(
+        "{:width$}  result  {:>10}  {:>12}",
+        "program", "time", "instructions"
+    );
+
+    let mut failures = 0;
+    for path in &programs {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let content = read_source(path, format);
+        let tokens = lexer::Lexer::new(content.as_str()).lex();
+
+        let input_path = args
+            .input_dir
+            .as_ref()
+            .map(|dir| dir.join(Path::new(&*name).with_extension("in")))
+            .filter(|path| path.is_file());
+        let input = match input_path {
+            Some(input_path) => match fs::File::open(&input_path) {
+                Ok(file) => Input::new(file),
+                Err(error) => {
+                    let message = format!("cannot read {}: {error}", input_path.display());
+                    report(&Diagnostic::error(Code::IoError, message), format);
+                    process::exit(1);
+                }
+            },
+            None => Input::new(io::empty()),
+        };
+
+        let command = BatchCommand {
+            input,
+            timeout: args.timeout,
+        };
+        let outcome = with_cell_width(args.cell_width, tokens, command);
+
+        let elapsed = format!("{:.3?}", outcome.elapsed);
+        match &outcome.result {
+            Ok(()) => println!(
+                "{name:width$}  ok      {elapsed:>10}  {:>12}",
+                outcome.steps
+            ),
+            Err(error) => {
+                failures += 1;
+                println!(
+                    "{name:width$}  FAILED  {elapsed:>10}  {:>12}  {error:#}",
+                    outcome.steps
+                );
+            }
+        }
+    }
+
+    println!();
+    println!("{} passed, {failures} failed", programs.len() - failures);
+    if failures > 0 {
+        process::exit(1);
+    }
+}
+
+struct CheckCommand {
+    program: ProgramArgs,
+    lints: LintConfig,
+    source: Source,
+    format: ErrorFormat,
+}
+
+impl CellCommand for CheckCommand {
+    type Output = ();
+
+    fn run<T: WhitespaceInt>(self, tokens: Vec<Token>) {
+        let instructions = parse_program::<T>(&self.program, &self.source, tokens, self.format);
+
+        lint(&instructions, &self.source, &self.lints, self.format);
+    }
+}
+
+struct DisasmCommand {
+    program: ProgramArgs,
+    source: Source,
+    format: ErrorFormat,
+}
+
+impl CellCommand for DisasmCommand {
+    type Output = ();
+
+    fn run<T: WhitespaceInt>(self, tokens: Vec<Token>) {
+        let instructions = parse_program::<T>(&self.program, &self.source, tokens, self.format);
+
+        for (i, instruction) in instructions.iter().enumerate() {
+            println!("{i}: {instruction}");
+        }
+    }
+}
+
+struct StatsCommand {
+    args: StatsArgs,
+    source: Source,
+    format: ErrorFormat,
+}
+
+impl CellCommand for StatsCommand {
+    type Output = ();
+
+    fn run<T: WhitespaceInt>(self, tokens: Vec<Token>) {
+        let count = tokens.len();
+        let instructions =
+            parse_program::<T>(&self.args.program, &self.source, tokens, self.format);
+        if self.args.size {
+            size_report(&instructions, instructions.starts(), count);
+            return;
+        }
+        let stats = stats::collect(&instructions, instructions.starts(), count);
+
+        println!("tokens        {}", stats.tokens);
+        println!("instructions  {}", stats.instructions);
+        println!("labels        {}", stats.labels);
+
+        let mut histogram: Vec<_> = stats.histogram.into_iter().collect();
+        histogram.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        println!();
+        println!("{:16}  {:>8}  {:>8}", "instruction", "count", "bytes");
+        for (name, count) in histogram {
+            println!("{name:16}  {count:>8}  {:>8}", stats.bytes[name]);
+        }
+
+        if !stats.literal_bits.is_empty() {
+            println!();
+            println!("{:16}  {:>8}", "literal bits", "count");
+            for (bits, count) in stats.literal_bits {
+                println!("{bits:<16}  {count:>8}");
+            }
+        }
+    }
+}
+
+/// Prints the sections and instructions of a program, largest first, with
+/// their size in bytes of whitespace.
+fn size_report<T: WhitespaceInt>(instructions: &[Instruction<T>], starts: &[usize], tokens: usize) {
+    let percent = |bytes: usize| 100.0 * bytes as f64 / tokens.max(1) as f64;
+
+    println!(
+        "{:>8}  {:>6}  {:>12}  section",
+        "bytes", "%", "instructions"
+    );
+    for section in stats::sections(instructions, starts, tokens) {
+        let name = match &section.label {
+            Some(label) => format!("label {} at instruction {}", letters(label), section.start),
+            None => "start of the program".to_string(),
+        };
+        println!(
+            "{:>8}  {:>5.1}%  {:>12}  {name}",
+            section.bytes,
+            percent(section.bytes),
+            section.instructions
+        );
+    }
+
+    let mut sizes: Vec<(usize, usize)> = stats::instruction_bytes(starts, tokens)
+        .into_iter()
+        .enumerate()
+        .collect();
+    sizes.sort_by(|(a, a_bytes), (b, b_bytes)| b_bytes.cmp(a_bytes).then(a.cmp(b)));
+    println!();
+    println!("{:>8}  {:>6}  {:>12}  instruction", "bytes", "%", "index");
+    for (i, bytes) in sizes.into_iter().take(10) {
+        println!(
+            "{bytes:>8}  {:>5.1}%  {i:>12}  {:?}",
+            percent(bytes),
+            instructions[i]
+        );
+    }
+}
+
+/// A label written with the letters S and T, as in the letters syntax.
+fn letters(label: &str) -> String {
+    match label.is_empty() {
+        true => "(empty)".to_string(),
+        false => label
+            .chars()
+            .map(|chr| if chr == ' ' { 'S' } else { 'T' })
+            .collect(),
+    }
+}
+
+struct VisualizeCommand {
+    args: VisualizeArgs,
+    source: Source,
+    format: ErrorFormat,
+}
+
+impl CellCommand for VisualizeCommand {
+    type Output = ();
+
+    fn run<T: WhitespaceInt>(self, tokens: Vec<Token>) {
+        let instructions = parse_program::<T>(
+            &self.args.program,
+            &self.source,
+            tokens.clone(),
+            self.format,
+        );
+        let starts = instructions.starts();
+        let sizes = stats::instruction_bytes(starts, tokens.len());
+
+        // the opcode and the argument of each instruction, as letters
+        let lines: Vec<(String, String)> = instructions
+            .iter()
+            .enumerate()
+            .map(|(i, instruction)| {
+                let tokens = &tokens[starts[i]..starts[i] + sizes[i]];
+                let (opcode, argument) =
+                    tokens.split_at(instruction.opcode_len().min(tokens.len()));
+                let letters = |tokens: &[Token]| tokens.iter().map(Token::as_letter).collect();
+                (letters(opcode), letters(argument))
+            })
+            .collect();
+
+        if self.args.html {
+            visualize_html(&instructions, &lines);
+            return;
+        }
+
+        let color = io::stdout().is_terminal();
+        let style = |chr| {
+            let style = match chr {
+                'S' => console::Style::new().cyan(),
+                'T' => console::Style::new().yellow(),
+                _ => console::Style::new().magenta(),
+            };
+            style.force_styling(color).apply_to(chr).to_string()
+        };
+        let width = lines
+            .iter()
+            .map(|(opcode, argument)| opcode.len() + argument.len() + 1)
+            .max()
+            .unwrap_or(0)
+            .min(40);
+        let index_width = instructions.len().to_string().len();
+
+        for (i, (instruction, (opcode, argument))) in instructions.iter().zip(&lines).enumerate() {
+            let padding = " ".repeat(width.saturating_sub(opcode.len() + argument.len() + 1));
+            let opcode: String = opcode.chars().map(style).collect();
+            let argument: String = argument.chars().map(style).collect();
+            println!("{i:>index_width$}  {opcode} {argument}{padding}  {instruction:?}");
+        }
+    }
+}
+
+/// Prints the visualized program as a standalone HTML page.
+fn visualize_html<T: fmt::Debug>(instructions: &[Instruction<T>], lines: &[(String, String)]) {
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let spans = |letters: &str| -> String {
+        letters
+            .chars()
+            .map(|chr| format!("<span class=\"{}\">{chr}</span>", chr.to_ascii_lowercase()))
+            .collect()
+    };
+
+    println!("<!DOCTYPE html>");
+    println!("<html>");
+    println!("<head>");
+    println!("<meta charset=\"utf-8\">");
+    println!("<title>Whitespace program</title>");
+    println!("<style>");
+    println!("table {{ font-family: monospace; border-collapse: collapse; }}");
+    println!("td {{ padding: 0 1em; vertical-align: top; }}");
+    println!("td.index {{ color: #888; text-align: right; }}");
+    println!(".s {{ color: #0a8; }} .t {{ color: #c80; }} .l {{ color: #a0a; }}");
+    println!("</style>");
+    println!("</head>");
+    println!("<body>");
+    println!("<table>");
+    for (i, (instruction, (opcode, argument))) in instructions.iter().zip(lines).enumerate() {
+        println!(
+            "<tr><td class=\"index\">{i}</td><td>{} {}</td><td>{}</td></tr>",
+            spans(opcode),
+            spans(argument),
+            escape(&format!("{instruction:?}"))
+        );
+    }
+    println!("</table>");
+    println!("</body>");
+    println!("</html>");
+}
+
+struct RunCommand {
+    args: RunArgs,
+    lints: LintConfig,
+    source: Source,
+    format: ErrorFormat,
+}
+
+impl CellCommand for RunCommand {
+    type Output = ();
+
+    fn run<T: WhitespaceInt>(self, tokens: Vec<Token>) {
+        run::<T>(self, tokens);
+    }
+}
+
+/// Addresses per row of an ASCII `--heap-heatmap`.
+const HEATMAP_WIDTH: usize = 64;
+
+fn run<T: WhitespaceInt>(command: RunCommand, tokens: Vec<Token>) {
+    let RunCommand {
+        args: cli,
+        lints,
+        source,
+        format,
+    } = command;
+
+    #[cfg(feature = "serde")]
+    let hash = program_hash(&tokens);
+    let program = parse_program::<T>(&cli.program, &source, tokens, format);
+    lint(&program, &source, &lints, format);
+
+    if let Some(Emit::Ast) = cli.emit {
+        for (i, instruction) in program.iter().enumerate() {
+            println!("{i}: {instruction:?}");
+        }
+        return;
+    }
+    if let Some(Emit::Whitespace) = cli.emit {
+        print!(
+            "{}",
+            Generator::new().minimize_labels(true).generate(&program)
+        );
+        return;
+    }
+    #[cfg(feature = "serde")]
+    if let Some(Emit::Json) = cli.emit {
+        let written = serde_json::to_writer(io::stdout().lock(), &program).map_err(io::Error::from);
+        write_or_exit(Path::new("stdout"), written, format);
+        println!();
+        return;
+    }
+
+    let expected_output = cli
+        .expect_output
+        .as_ref()
+        .map(|path| read_source(path, format));
+
+    let mut vm = match cli.heap_size {
+        Some(size) => interpreter::VM::<T>::with_heap_size(size.min(DEFAULT_HEAP_SIZE)),
+        None => interpreter::VM::<T>::new(),
+    };
+    #[cfg(feature = "serde")]
+    let resumed = match &cli.resume {
+        Some(path) => {
+            vm = read_checkpoint(path, &hash, format);
+            true
+        }
+        None => false,
+    };
+    #[cfg(not(feature = "serde"))]
+    let resumed = false;
+
+    vm.max_heap_size = cli.heap_size;
+    vm.max_call_depth = cli.max_call_depth;
+    vm.max_stack = cli.max_stack;
+    // a resumed program gets its fuel on top of the steps it already took
+    vm.fuel = cli.fuel.map(|fuel| vm.steps.saturating_add(fuel));
+    vm.implicit_exit = cli.allow_implicit_exit;
+    vm.exit_code_from_stack = cli.exit_code_from_stack;
+    if cli.trap_uninit_heap {
+        vm.heap.track_initialized();
+    }
+    if let (false, Some(args)) = (resumed, &cli.args) {
+        let convention = match cli.args_convention {
+            ArgsLayout::Argv => ArgsConvention::Argv {
+                address: cli.args_at,
+            },
+            ArgsLayout::Heap => ArgsConvention::Heap {
+                address: cli.args_at,
+            },
+            ArgsLayout::Stack => ArgsConvention::Stack,
+        };
+        if let Err(error) = vm.preload_args_with(args, convention) {
+            report(
+                &Diagnostic::error(Code::RuntimeError, format!("{error:#}")),
+                format,
+            );
+            process::exit(1);
+        }
+    }
+    // a resumed heap already holds whatever was preloaded into it
+    if let (false, Some(path)) = (resumed, &cli.heap_init) {
+        let loaded = fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| {
+                let values = bytes.into_iter().map(|byte| T::from_char(byte.into()));
+                vm.preload_heap(cli.heap_init_at, values)
+                    .map_err(anyhow::Error::from)
+            });
+        preload_or_exit(path, loaded.map(drop), format);
+    }
+    #[cfg(feature = "serde")]
+    if let (false, Some(path)) = (resumed, &cli.heap_init_json) {
+        let loaded = read_heap_json(path, cli.heap_init_at).and_then(|cells| {
+            cells
+                .into_iter()
+                .try_for_each(|(address, value)| vm.preload_heap(address, [value]).map(drop))
+                .map_err(anyhow::Error::from)
+        });
+        preload_or_exit(path, loaded, format);
+    }
+    vm.io = match cli.io {
+        Io::Std => IoMode::Terminal,
+        Io::Null => IoMode::Null,
+    };
+    vm.overflow = match cli.overflow {
+        Overflow::Trap => OverflowPolicy::Trap,
+        Overflow::Wrap => OverflowPolicy::Wrap,
+        Overflow::Saturate => OverflowPolicy::Saturate,
+    };
+    vm.eof = match cli.eof {
+        Eof::Error => EofBehavior::Error,
+        Eof::MinusOne => EofBehavior::MinusOne,
+        Eof::Zero => EofBehavior::Zero,
+    };
+    vm.division = match cli.division {
+        Division::Floor => DivisionSemantics::Floor,
+        Division::Truncate => DivisionSemantics::Truncate,
+    };
+    let input = cli.input.as_ref().map(|path| {
+        let file = fs::File::open(path).unwrap_or_else(|error| {
+            let message = format!("cannot read {}: {error}", path.display());
+            report(&Diagnostic::error(Code::IoError, message), format);
+            process::exit(1);
+        });
+        Input::new(file)
+    });
+    let output = cli.output.as_ref().map(|path| {
+        let file = fs::File::create(path).unwrap_or_else(|error| {
+            let message = format!("cannot write {}: {error}", path.display());
+            report(&Diagnostic::error(Code::IoError, message), format);
+            process::exit(1);
+        });
+        Output::new(file)
+    });
+    let terminal = Terminal {
+        char_input: match cli.char_input {
+            CharInputMode::Auto => CharInput::Auto,
+            CharInputMode::Console => CharInput::Console,
+            CharInputMode::Stdin => CharInput::Stdin,
+        },
+        prompts: (!cli.no_prompt && io::stdin().is_terminal()).then_some(Prompts {
+            number: cli.number_prompt,
+            char: cli.char_prompt,
+        }),
+        ..Terminal::default()
+    };
+    vm.host_io = terminal.redirect(input, output);
+    if cli.heap_heatmap.is_some() {
+        vm.heap_heatmap = Some(HeapHeatmap::default());
+    }
+    if expected_output.is_some() {
+        vm.capture_output();
+    }
+    if cli.stack_timeline.is_some() {
+        vm.stack_timeline = Some(StackTimeline::new(cli.stack_timeline_interval));
+    }
+    match &cli.trace {
+        Some(Some(path)) => {
+            let file = fs::File::create(path).unwrap_or_else(|error| {
+                let message = format!("cannot write {}: {error}", path.display());
+                report(&Diagnostic::error(Code::IoError, message), format);
+                process::exit(1);
+            });
+            vm.tracer = Some(Tracer::new(file, cli.trace_limit));
+        }
+        Some(None) => vm.tracer = Some(Tracer::new(io::stderr(), cli.trace_limit)),
+        None => {}
+    }
+
+    let interrupt = match cli.timeout {
+        Some(timeout) => Interrupt::after(timeout),
+        None => Interrupt::default(),
+    };
+    // the first Ctrl+C stops the program at its next instruction so its state
+    // can be shown; a second one exits right away, e.g. while waiting for input
+    let ctrl_c = Arc::new(AtomicBool::new(false));
+    let handler = {
+        let interrupt = interrupt.clone();
+        let ctrl_c = Arc::clone(&ctrl_c);
+        ctrlc::set_handler(move || {
+            if ctrl_c.swap(true, Ordering::Relaxed) {
+                process::exit(130);
+            }
+            interrupt.trigger();
+        })
+    };
+    if let Err(error) = handler {
+        tracing::warn!(%error, "cannot handle Ctrl+C");
+    }
+    vm.interrupt = Some(interrupt);
+
+    let mut interpreter = interpreter::Interpreter::new(vm);
+    interpreter.load(program);
+    let result = match cli.delay {
+        Some(delay) => run_slowly(
+            &mut interpreter,
+            Duration::from_millis(delay),
+            cli.show_stack,
+        ),
+        None => interpreter.run().map_err(anyhow::Error::from),
+    };
+    let result = result.map_err(|error| match (cli.timeout, is_interrupted(&error)) {
+        (Some(timeout), true) if !ctrl_c.load(Ordering::Relaxed) => {
+            error.context(format!("timed out after {timeout:?}"))
+        }
+        _ => error,
+    });
+
+    #[cfg(feature = "serde")]
+    let (result, checkpointed) = match (result, &cli.checkpoint) {
+        (Err(error), Some(path)) if is_interrupted(&error) || is_out_of_fuel(&error) => {
+            let checkpoint = Checkpoint {
+                program: hash,
+                vm: interpreter.vm(),
+            };
+            let written = fs::File::create(path).and_then(|file| {
+                serde_json::to_writer(io::BufWriter::new(file), &checkpoint)
+                    .map_err(io::Error::from)
+            });
+            write_or_exit(path, written, format);
+            eprintln!("{error:#}, state saved to {}", path.display());
+            (Ok(()), true)
+        }
+        (result, _) => (result, false),
+    };
+    #[cfg(not(feature = "serde"))]
+    let checkpointed = false;
+
+    if let Some(path) = &cli.output {
+        write_or_exit(path, interpreter.vm_mut().host_io.flush(), format);
+    }
+    if let (Some(trace), Some(tracer)) = (&cli.trace, &mut interpreter.vm_mut().tracer) {
+        let path = trace.as_deref().unwrap_or(Path::new("stderr"));
+        write_or_exit(path, tracer.flush(), format);
+    }
+
+    if ctrl_c.load(Ordering::Relaxed) && result.as_ref().is_err_and(is_interrupted) {
+        let stack = interpreter.stack();
+        let top = &stack[stack.len().saturating_sub(5)..];
+        eprintln!();
+        eprintln!(
+            "interrupted at instruction {}",
+            interpreter.instruction_ptr()
+        );
+        eprintln!("stack depth: {}", stack.len());
+        eprintln!("top of stack: {top:?}");
+        match interpreter.heap().high_water_mark() {
+            Some(address) => eprintln!("heap high-water mark: {address}"),
+            None => eprintln!("heap high-water mark: none"),
+        }
+        let _ = io::stdout().flush();
+        process::exit(130);
+    }
+
+    let failed = result.is_err();
+    if let Err(error) = result {
+        let broken_pipe = matches!(
+            error.downcast_ref::<WsError>(),
+            Some(WsError::IoError { source, .. }) if source.kind() == io::ErrorKind::BrokenPipe
+        );
+
+        if broken_pipe {
+            if !cli.strict_io {
+                process::exit(0);
+            }
+
+            report(
+                &Diagnostic::error(Code::IoError, format!("{error:#}")),
+                format,
+            );
+            process::exit(1);
+        }
+
+        let ip = interpreter.instruction_ptr();
+        let span = interpreter
+            .program()
+            .starts()
+            .get(ip)
+            .and_then(|&token| source.span_of(token));
+        match format {
+            ErrorFormat::Human => {
+                eprintln!("error was: {error:#}");
+                eprintln!("instruction pointer: {ip}");
+                eprintln!("stack: {:?}", interpreter.stack());
+                eprintln!("heap: {:?}", interpreter.heap().cells());
+                if let Some(span) = span {
+                    eprintln!("{}", source.excerpt(span));
+                }
+            }
+            ErrorFormat::Json => {
+                let mut diagnostic = Diagnostic::error(Code::RuntimeError, format!("{error:#}"))
+                    .with_instruction(ip)
+                    .with_stack(interpreter.stack());
+                diagnostic.span = span;
+                let label = interpreter.program().get(ip).and_then(Instruction::label);
+                if let Some(label) = label {
+                    diagnostic = diagnostic.with_label(label);
+                }
+                report(&diagnostic, format);
+            }
+        }
+    }
+
+    let vm = interpreter.vm();
+    if let (Some(path), Some(heatmap)) = (&cli.heap_heatmap, &vm.heap_heatmap) {
+        let written = fs::File::create(path).and_then(|file| match cli.heatmap_format {
+            HeatmapFormat::Csv => heatmap.write_csv(file),
+            HeatmapFormat::Ascii => heatmap.write_ascii(file, HEATMAP_WIDTH),
+        });
+        write_or_exit(path, written, format);
+    }
+    if let (Some(path), Some(timeline)) = (&cli.stack_timeline, &vm.stack_timeline) {
+        let written = fs::File::create(path).and_then(|file| timeline.write_csv(file));
+        write_or_exit(path, written, format);
+    }
+
+    if let (Some(expected), Some(actual)) = (&expected_output, vm.captured_output()) {
+        if expected != actual {
+            eprintln!("output does not match expected output:");
+            print_diff(expected, actual);
+            process::exit(1);
+        }
+    }
+
+    // the program didn't finish, so scripts mustn't mistake it for a success
+    if checkpointed {
+        process::exit(130);
+    }
+    if failed {
+        process::exit(1);
+    }
+    if let Some(code) = &vm.exit_code {
+        // like a shell, keep the low byte; negative codes wrap around
+        let code = T::from_usize(256)
+            .and_then(|modulus| code.rem_with(&modulus, DivisionSemantics::Floor))
+            .and_then(|code| code.to_u32())
+            .unwrap_or(1);
+        process::exit(code as i32);
+    }
+}
+
+/// Whether the program was stopped by its [`Interrupt`].
+fn is_interrupted(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<WsError>(),
+        Some(WsError::Interrupted(_))
+    )
+}
+
+/// Whether the program ran out of the steps given with `--fuel`.
+#[cfg(feature = "serde")]
+fn is_out_of_fuel(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<WsError>(),
+        Some(WsError::FuelExhausted(_))
+    )
+}
+
+/// Runs the loaded program one instruction at a time, sleeping `delay` before
+/// each one and optionally redrawing the stack on stderr.
+fn run_slowly<E: Executor>(
+    executor: &mut E,
+    delay: Duration,
+    show_stack: bool,
+) -> anyhow::Result<()>
+where
+    E::Value: fmt::Display,
+{
+    let term = console::Term::stderr();
+    let show_stack = show_stack && term.is_term();
+
+    loop {
+        if show_stack {
+            let stack: Vec<String> = executor.stack().iter().map(ToString::to_string).collect();
+            let status = format!(
+                "ip {}, stack [{}]",
+                executor.instruction_ptr(),
+                stack.join(", ")
+            );
+            let _ = io::stdout().flush();
+            let _ = term.clear_line();
+            let _ = term.write_str(&status);
+        }
+
+        thread::sleep(delay);
+
+        if show_stack {
+            let _ = term.clear_line();
+        }
+        if !executor.step()? {
+            return Ok(());
+        }
+    }
+}
+
+/// Prints a line diff between `expected` and `actual` to stderr.
+fn print_diff(expected: &str, actual: &str) {
+    let expected: Vec<&str> = expected.split_inclusive('\n').collect();
+    let actual: Vec<&str> = actual.split_inclusive('\n').collect();
+
+    // lengths of the longest common subsequences of the suffixes
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let line = |prefix: char, text: &str| match text.strip_suffix('\n') {
+        Some(text) => eprintln!("{prefix}{text}"),
+        None => eprintln!("{prefix}{text}\n\\ no newline at end"),
+    };
+
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            line(' ', expected[i]);
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            line('+', actual[j]);
+            j += 1;
+        } else {
+            line('-', expected[i]);
+            i += 1;
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn read_checkpoint<T: WhitespaceInt>(
+    path: &Path,
+    hash: &str,
+    format: ErrorFormat,
+) -> interpreter::VM<T> {
+    let state = fs::File::open(path).and_then(|file| {
+        serde_json::from_reader(io::BufReader::new(file)).map_err(io::Error::from)
+    });
+
+    let message = match state {
+        Ok(Checkpoint { program, vm }) if program == hash => return vm,
+        Ok(_) => format!(
+            "cannot resume from {}: it was saved from another program",
+            path.display()
+        ),
+        Err(error) => format!("cannot resume from {}: {error}", path.display()),
+    };
+    report(&Diagnostic::error(Code::IoError, message), format);
+    process::exit(1);
+}
+
+/// Reads heap cells from a JSON array starting at `start`, or from an object
+/// of `"address": value` pairs.
+#[cfg(feature = "serde")]
+fn read_heap_json<T: WhitespaceInt>(path: &Path, start: usize) -> anyhow::Result<Vec<(usize, T)>> {
+    use anyhow::anyhow;
+    use serde_json::Value;
+
+    let cell = |value: &Value| {
+        value
+            .as_number()
+            .and_then(|number| number.to_string().parse().ok())
+            .ok_or_else(|| anyhow!("{value} is not a cell value"))
+    };
+
+    match serde_json::from_str(&fs::read_to_string(path)?)? {
+        Value::Array(values) => values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Ok((start + i, cell(value)?)))
+            .collect(),
+        Value::Object(cells) => cells
+            .iter()
+            .map(|(address, value)| {
+                let address = address
+                    .parse()
+                    .map_err(|_| anyhow!("{address:?} is not a heap address"))?;
+                Ok((address, cell(value)?))
+            })
+            .collect(),
+        _ => Err(anyhow!("expected an array or an object")),
+    }
+}
+
+fn preload_or_exit(path: &Path, loaded: anyhow::Result<()>, format: ErrorFormat) {
+    if let Err(error) = loaded {
+        let message = format!("cannot load {} into the heap: {error:#}", path.display());
+        report(&Diagnostic::error(Code::IoError, message), format);
+        process::exit(1);
+    }
+}
 
-    let mut vm = interpreter::VM::new();
-    if let Err(error) = vm.execute(&parser.output) {
-        println!("error was: {error}");
-        println!("stack: {:?}", vm.stack);
-        println!("heap: {:?}", vm.heap);
+fn write_or_exit(path: &Path, written: io::Result<()>, format: ErrorFormat) {
+    if let Err(error) = written {
+        let message = format!("cannot write {}: {error}", path.display());
+        report(&Diagnostic::error(Code::IoError, message), format);
+        process::exit(1);
     }
 }
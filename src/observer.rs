@@ -0,0 +1,88 @@
+use std::fmt;
+
+use crate::parser::Instruction;
+
+/// Callbacks run by the VM as it executes, for building profilers, tracers,
+/// debuggers and coverage tools outside the interpreter loop.
+///
+/// Register one with [`VM::observe`](crate::VM::observe). Every method does
+/// nothing by default.
+pub trait Observer<T>: Send {
+    /// Called with the instruction at index `ip` and the stack it is about
+    /// to run on.
+    fn before_instruction(&mut self, _ip: usize, _instruction: &Instruction<T>, _stack: &[T]) {}
+
+    /// Called once the instruction at index `ip` has run, with the stack it
+    /// left behind.
+    fn after_instruction(&mut self, _ip: usize, _instruction: &Instruction<T>, _stack: &[T]) {}
+
+    /// Called when a Call, Jump, taken conditional jump or EndSubroutine at
+    /// index `from` moves execution; `to` is the next instruction to run.
+    fn on_jump(&mut self, _from: usize, _to: usize) {}
+
+    /// Called after the I/O instruction at index `ip` ran, with the value it
+    /// printed or read.
+    fn on_io(&mut self, _ip: usize, _instruction: &Instruction<T>, _value: &T) {}
+}
+
+impl<T> fmt::Debug for dyn Observer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::VM;
+
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl Observer<i32> for Arc<Mutex<Log>> {
+        fn before_instruction(&mut self, ip: usize, _instruction: &Instruction, stack: &[i32]) {
+            self.lock().unwrap().0.push(format!("{ip} {stack:?}"));
+        }
+
+        fn on_jump(&mut self, from: usize, to: usize) {
+            self.lock().unwrap().0.push(format!("jump {from} -> {to}"));
+        }
+
+        fn on_io(&mut self, ip: usize, _instruction: &Instruction, value: &i32) {
+            self.lock().unwrap().0.push(format!("io {ip} {value}"));
+        }
+    }
+
+    #[test]
+    fn callbacks() {
+        let log = Arc::new(Mutex::new(Log::default()));
+        let mut vm: VM = VM::builder().output(std::io::sink()).build();
+        vm.observe(log.clone());
+
+        vm.execute(&[
+            Instruction::Push(7),
+            Instruction::Push(0),
+            Instruction::JumpIfZero(" ".to_string()),
+            Instruction::Push(1),
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::OutputNumber,
+            Instruction::EndProgram,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            log.lock().unwrap().0,
+            vec![
+                "0 []",
+                "1 [7]",
+                "2 [7, 0]",
+                "jump 2 -> 5",
+                "5 [7]",
+                "io 5 7",
+                "6 []"
+            ]
+        );
+    }
+}
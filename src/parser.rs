@@ -1,14 +1,20 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cell::WhitespaceInt;
+use crate::error::{Result, WsError};
 use crate::lexer::Token;
-use anyhow::{bail, Result};
+use crate::program::Program;
 
-#[derive(Debug)]
-pub enum Instruction {
-    Push(i32),
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Instruction<T = i32> {
+    Push(T),
     Duplicate,
-    Copy(i32),
+    Copy(T),
     Swap,
     Discard,
-    Slide(i32),
+    Slide(T),
     Add,
     Substract,
     Multiply,
@@ -29,19 +35,273 @@ pub enum Instruction {
     ReadNumber,
 }
 
+impl<T> Instruction<T> {
+    /// The label the instruction marks or jumps to, if any.
+    pub fn label(&self) -> Option<&str> {
+        match self {
+            Instruction::MarkLocation(label)
+            | Instruction::Call(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpIfZero(label)
+            | Instruction::JumpIfNegative(label) => Some(label),
+            _ => None,
+        }
+    }
+
+    /// Name of the kind of instruction, without its argument.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::Push(_) => "Push",
+            Instruction::Duplicate => "Duplicate",
+            Instruction::Copy(_) => "Copy",
+            Instruction::Swap => "Swap",
+            Instruction::Discard => "Discard",
+            Instruction::Slide(_) => "Slide",
+            Instruction::Add => "Add",
+            Instruction::Substract => "Substract",
+            Instruction::Multiply => "Multiply",
+            Instruction::Divide => "Divide",
+            Instruction::Modulo => "Modulo",
+            Instruction::HeapStore => "HeapStore",
+            Instruction::HeapRetrieve => "HeapRetrieve",
+            Instruction::MarkLocation(_) => "MarkLocation",
+            Instruction::Call(_) => "Call",
+            Instruction::Jump(_) => "Jump",
+            Instruction::JumpIfZero(_) => "JumpIfZero",
+            Instruction::JumpIfNegative(_) => "JumpIfNegative",
+            Instruction::EndSubroutine => "EndSubroutine",
+            Instruction::EndProgram => "EndProgram",
+            Instruction::OutputChar => "OutputChar",
+            Instruction::OutputNumber => "OutputNumber",
+            Instruction::ReadChar => "ReadChar",
+            Instruction::ReadNumber => "ReadNumber",
+        }
+    }
+
+    /// Number of tokens before the argument of the instruction, or in the
+    /// whole instruction if it has none.
+    pub fn opcode_len(&self) -> usize {
+        match self {
+            Instruction::Push(_) => 2,
+            Instruction::Duplicate
+            | Instruction::Copy(_)
+            | Instruction::Swap
+            | Instruction::Discard
+            | Instruction::Slide(_)
+            | Instruction::HeapStore
+            | Instruction::HeapRetrieve
+            | Instruction::MarkLocation(_)
+            | Instruction::Call(_)
+            | Instruction::Jump(_)
+            | Instruction::JumpIfZero(_)
+            | Instruction::JumpIfNegative(_)
+            | Instruction::EndSubroutine
+            | Instruction::EndProgram => 3,
+            Instruction::Add
+            | Instruction::Substract
+            | Instruction::Multiply
+            | Instruction::Divide
+            | Instruction::Modulo
+            | Instruction::OutputChar
+            | Instruction::OutputNumber
+            | Instruction::ReadChar
+            | Instruction::ReadNumber => 4,
+        }
+    }
+
+    /// Number of items that must be on the stack for the instruction to run.
+    pub fn stack_arguments(&self) -> usize {
+        match self {
+            Instruction::Swap
+            | Instruction::Add
+            | Instruction::Substract
+            | Instruction::Multiply
+            | Instruction::Divide
+            | Instruction::Modulo
+            | Instruction::HeapStore => 2,
+            Instruction::Duplicate
+            | Instruction::Discard
+            | Instruction::Slide(_)
+            | Instruction::HeapRetrieve
+            | Instruction::JumpIfZero(_)
+            | Instruction::JumpIfNegative(_)
+            | Instruction::OutputChar
+            | Instruction::OutputNumber
+            | Instruction::ReadChar
+            | Instruction::ReadNumber => 1,
+            Instruction::Push(_)
+            | Instruction::Copy(_)
+            | Instruction::MarkLocation(_)
+            | Instruction::Call(_)
+            | Instruction::Jump(_)
+            | Instruction::EndSubroutine
+            | Instruction::EndProgram => 0,
+        }
+    }
+}
+
+/// Writes the instruction as an assembly mnemonic, such as `push 42` or
+/// `jz @STS`; labels are written as their spaces and tabs spelled `S` and
+/// `T`, after an `@`.
+impl<T: fmt::Display> fmt::Display for Instruction<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = |label: &str| -> String {
+            label
+                .chars()
+                .map(|chr| if chr == '\t' { 'T' } else { 'S' })
+                .collect()
+        };
+
+        match self {
+            Instruction::Push(number) => write!(f, "push {number}"),
+            Instruction::Duplicate => f.write_str("dup"),
+            Instruction::Copy(number) => write!(f, "copy {number}"),
+            Instruction::Swap => f.write_str("swap"),
+            Instruction::Discard => f.write_str("drop"),
+            Instruction::Slide(number) => write!(f, "slide {number}"),
+            Instruction::Add => f.write_str("add"),
+            Instruction::Substract => f.write_str("sub"),
+            Instruction::Multiply => f.write_str("mul"),
+            Instruction::Divide => f.write_str("div"),
+            Instruction::Modulo => f.write_str("mod"),
+            Instruction::HeapStore => f.write_str("store"),
+            Instruction::HeapRetrieve => f.write_str("retrieve"),
+            Instruction::MarkLocation(name) => write!(f, "label @{}", label(name)),
+            Instruction::Call(name) => write!(f, "call @{}", label(name)),
+            Instruction::Jump(name) => write!(f, "jmp @{}", label(name)),
+            Instruction::JumpIfZero(name) => write!(f, "jz @{}", label(name)),
+            Instruction::JumpIfNegative(name) => write!(f, "jn @{}", label(name)),
+            Instruction::EndSubroutine => f.write_str("ret"),
+            Instruction::EndProgram => f.write_str("end"),
+            Instruction::OutputChar => f.write_str("printc"),
+            Instruction::OutputNumber => f.write_str("printn"),
+            Instruction::ReadChar => f.write_str("readc"),
+            Instruction::ReadNumber => f.write_str("readn"),
+        }
+    }
+}
+
+/// Reads back a mnemonic written by the [`Display`](fmt::Display)
+/// implementation.
+impl<T: FromStr> FromStr for Instruction<T> {
+    type Err = WsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || WsError::InvalidInstruction(s.trim().to_string());
+        let number = |argument: &str| argument.parse().map_err(|_| invalid());
+        let label = |argument: &str| {
+            argument
+                .strip_prefix('@')
+                .and_then(|letters| {
+                    letters
+                        .chars()
+                        .map(|chr| match chr {
+                            'S' => Some(' '),
+                            'T' => Some('\t'),
+                            _ => None,
+                        })
+                        .collect()
+                })
+                .ok_or_else(invalid)
+        };
+
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let instruction = match words[..] {
+            ["push", argument] => Instruction::Push(number(argument)?),
+            ["dup"] => Instruction::Duplicate,
+            ["copy", argument] => Instruction::Copy(number(argument)?),
+            ["swap"] => Instruction::Swap,
+            ["drop"] => Instruction::Discard,
+            ["slide", argument] => Instruction::Slide(number(argument)?),
+            ["add"] => Instruction::Add,
+            ["sub"] => Instruction::Substract,
+            ["mul"] => Instruction::Multiply,
+            ["div"] => Instruction::Divide,
+            ["mod"] => Instruction::Modulo,
+            ["store"] => Instruction::HeapStore,
+            ["retrieve"] => Instruction::HeapRetrieve,
+            ["label", argument] => Instruction::MarkLocation(label(argument)?),
+            ["call", argument] => Instruction::Call(label(argument)?),
+            ["jmp", argument] => Instruction::Jump(label(argument)?),
+            ["jz", argument] => Instruction::JumpIfZero(label(argument)?),
+            ["jn", argument] => Instruction::JumpIfNegative(label(argument)?),
+            ["ret"] => Instruction::EndSubroutine,
+            ["end"] => Instruction::EndProgram,
+            ["printc"] => Instruction::OutputChar,
+            ["printn"] => Instruction::OutputNumber,
+            ["readc"] => Instruction::ReadChar,
+            ["readn"] => Instruction::ReadNumber,
+            _ => return Err(invalid()),
+        };
+
+        Ok(instruction)
+    }
+}
+
+/// A number literal has more significant bits than the cell type holds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct LiteralOverflow {
+    pub bits: usize,
+    /// Index of the literal's sign token.
+    pub token: usize,
+}
+
+impl fmt::Display for LiteralOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "number literal of {} bits at token {} does not fit in a cell",
+            self.bits, self.token
+        )
+    }
+}
+
+impl std::error::Error for LiteralOverflow {}
+
+/// Upper bounds enforced while parsing untrusted programs.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ParseLimits {
+    pub max_instructions: Option<usize>,
+    pub max_label_length: Option<usize>,
+    pub max_number_bits: Option<usize>,
+}
+
+/// An error found by [`Parser::parse_all`].
 #[derive(Debug)]
-pub struct Parser {
+pub struct ParseProblem {
+    /// Index of the token where the offending instruction or literal starts.
+    pub token: usize,
+    pub error: WsError,
+}
+
+#[derive(Debug)]
+pub struct Parser<T = i32> {
     input: Vec<Token>,
     current: usize,
-    pub output: Vec<Instruction>,
+    /// Index of the first token of the instruction being parsed.
+    start: usize,
+    limits: ParseLimits,
+    /// Literal overflows skipped over while running [`Parser::parse_all`].
+    overflows: Option<Vec<LiteralOverflow>>,
+    output: Vec<Instruction<T>>,
+    /// Index of the first token of each instruction in `output`.
+    starts: Vec<usize>,
 }
 
-impl Parser {
+impl<T: WhitespaceInt> Parser<T> {
     pub fn new(tokens: Vec<Token>) -> Self {
+        Self::with_limits(tokens, ParseLimits::default())
+    }
+
+    pub fn with_limits(tokens: Vec<Token>, limits: ParseLimits) -> Self {
         Self {
             input: tokens,
             current: 0,
+            start: 0,
+            limits,
+            overflows: None,
             output: Vec::new(),
+            starts: Vec::new(),
         }
     }
 
@@ -49,36 +309,106 @@ impl Parser {
         self.current >= self.input.len()
     }
 
-    fn advance(&mut self) -> &Token {
+    fn advance(&mut self) -> Result<Token> {
+        let token = self.input.get(self.current).cloned().ok_or_else(|| {
+            self.error(format!(
+                "unexpected end of program after token {}",
+                self.current
+            ))
+        })?;
         self.current += 1;
-        &self.input[self.current - 1]
+
+        Ok(token)
     }
 
-    pub fn parse(&mut self) -> Result<()> {
+    fn error(&self, message: impl Into<String>) -> WsError {
+        WsError::ParseError {
+            offset: self.start,
+            message: message.into(),
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn parse(mut self) -> Result<Program<T>> {
         while !self.is_at_end() {
-            match self.advance() {
-                Token::Tab => match self.advance() {
-                    Token::Space => self.parse_arithmetic()?,
-                    Token::Tab => self.parse_heap_access()?,
-                    Token::LineFeed => self.parse_input_output()?,
-                },
-                Token::Space => self.parse_stack_manipulation()?,
-                Token::LineFeed => self.parse_flow_control()?,
-            };
+            let start = self.current;
+            self.parse_instruction()?;
+            self.starts.push(start);
         }
 
-        Ok(())
+        tracing::debug!(instructions = self.output.len(), "parsed program");
+        Ok(Program::with_starts(self.output, self.starts))
+    }
+
+    /// Parses as much of the program as possible, returning what could be
+    /// parsed along with every problem found instead of stopping at the first
+    /// one.
+    ///
+    /// Literals that don't fit in a cell are reported and parsed as 0. Any
+    /// other error ends parsing, as the tokens after it can't be made sense of.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn parse_all(mut self) -> (Program<T>, Vec<ParseProblem>) {
+        self.overflows = Some(Vec::new());
+        let mut start = self.current;
+        let mut result = Ok(());
+
+        while !self.is_at_end() && result.is_ok() {
+            start = self.current;
+            result = self.parse_instruction();
+            if result.is_ok() {
+                self.starts.push(start);
+            }
+        }
+
+        let mut problems: Vec<ParseProblem> = self
+            .overflows
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|overflow| ParseProblem {
+                token: overflow.token,
+                error: overflow.into(),
+            })
+            .collect();
+        if let Err(error) = result {
+            problems.push(ParseProblem {
+                token: start,
+                error,
+            });
+        }
+
+        (Program::with_starts(self.output, self.starts), problems)
+    }
+
+    fn parse_instruction(&mut self) -> Result<()> {
+        self.start = self.current;
+
+        if let Some(max) = self.limits.max_instructions {
+            if self.output.len() >= max {
+                return Err(self.error(format!("program exceeds the limit of {max} instructions")));
+            }
+        }
+
+        match self.advance()? {
+            Token::Tab => match self.advance()? {
+                Token::Space => self.parse_arithmetic(),
+                Token::Tab => self.parse_heap_access(),
+                Token::LineFeed => self.parse_input_output(),
+            },
+            Token::Space => self.parse_stack_manipulation(),
+            Token::LineFeed => self.parse_flow_control(),
+        }
     }
 
     fn parse_stack_manipulation(&mut self) -> Result<()> {
-        let instruction = match self.advance() {
+        let instruction = match self.advance()? {
             Token::Space => Instruction::Push(self.parse_number()?),
-            Token::Tab => match self.advance() {
+            Token::Tab => match self.advance()? {
                 Token::Space => Instruction::Copy(self.parse_number()?),
                 Token::LineFeed => Instruction::Slide(self.parse_number()?),
-                _ => bail!("invalid stack manipulation instruction"),
+                _ => return Err(self.error("invalid stack manipulation instruction")),
             },
-            Token::LineFeed => match self.advance() {
+            Token::LineFeed => match self.advance()? {
                 Token::Tab => Instruction::Swap,
                 Token::LineFeed => Instruction::Discard,
                 Token::Space => Instruction::Duplicate,
@@ -91,18 +421,18 @@ impl Parser {
     }
 
     fn parse_arithmetic(&mut self) -> Result<()> {
-        let instruction = match self.advance() {
-            Token::Space => match self.advance() {
+        let instruction = match self.advance()? {
+            Token::Space => match self.advance()? {
                 Token::Space => Instruction::Add,
                 Token::Tab => Instruction::Substract,
                 Token::LineFeed => Instruction::Multiply,
             },
-            Token::Tab => match self.advance() {
+            Token::Tab => match self.advance()? {
                 Token::Space => Instruction::Divide,
                 Token::Tab => Instruction::Modulo,
-                _ => bail!("invalid arithmetic instruction"),
+                _ => return Err(self.error("invalid arithmetic instruction")),
             },
-            _ => bail!("invalid arithmetic instruction"),
+            _ => return Err(self.error("invalid arithmetic instruction")),
         };
 
         self.output.push(instruction);
@@ -111,10 +441,10 @@ impl Parser {
     }
 
     fn parse_heap_access(&mut self) -> Result<()> {
-        let instruction = match self.advance() {
+        let instruction = match self.advance()? {
             Token::Space => Instruction::HeapStore,
             Token::Tab => Instruction::HeapRetrieve,
-            _ => bail!("invalid heap instruction"),
+            _ => return Err(self.error("invalid heap instruction")),
         };
 
         self.output.push(instruction);
@@ -123,20 +453,20 @@ impl Parser {
     }
 
     fn parse_flow_control(&mut self) -> Result<()> {
-        let instruction = match self.advance() {
-            Token::Space => match self.advance() {
-                Token::Space => Instruction::MarkLocation(self.parse_label()),
-                Token::Tab => Instruction::Call(self.parse_label()),
-                Token::LineFeed => Instruction::Jump(self.parse_label()),
+        let instruction = match self.advance()? {
+            Token::Space => match self.advance()? {
+                Token::Space => Instruction::MarkLocation(self.parse_label()?),
+                Token::Tab => Instruction::Call(self.parse_label()?),
+                Token::LineFeed => Instruction::Jump(self.parse_label()?),
             },
-            Token::Tab => match self.advance() {
-                Token::Space => Instruction::JumpIfZero(self.parse_label()),
-                Token::Tab => Instruction::JumpIfNegative(self.parse_label()),
+            Token::Tab => match self.advance()? {
+                Token::Space => Instruction::JumpIfZero(self.parse_label()?),
+                Token::Tab => Instruction::JumpIfNegative(self.parse_label()?),
                 Token::LineFeed => Instruction::EndSubroutine,
             },
-            Token::LineFeed => match self.advance() {
+            Token::LineFeed => match self.advance()? {
                 Token::LineFeed => Instruction::EndProgram,
-                _ => bail!("invalid flow control instruction"),
+                _ => return Err(self.error("invalid flow control instruction")),
             },
         };
 
@@ -146,18 +476,18 @@ impl Parser {
     }
 
     fn parse_input_output(&mut self) -> Result<()> {
-        let instruction = match self.advance() {
-            Token::Space => match self.advance() {
+        let instruction = match self.advance()? {
+            Token::Space => match self.advance()? {
                 Token::Space => Instruction::OutputChar,
                 Token::Tab => Instruction::OutputNumber,
-                _ => bail!("invalid i/o instruction"),
+                _ => return Err(self.error("invalid i/o instruction")),
             },
-            Token::Tab => match self.advance() {
+            Token::Tab => match self.advance()? {
                 Token::Space => Instruction::ReadChar,
                 Token::Tab => Instruction::ReadNumber,
-                _ => bail!("invalid i/o instruction"),
+                _ => return Err(self.error("invalid i/o instruction")),
             },
-            _ => bail!("invalid i/o instruction"),
+            _ => return Err(self.error("invalid i/o instruction")),
         };
 
         self.output.push(instruction);
@@ -165,45 +495,69 @@ impl Parser {
         Ok(())
     }
 
-    fn parse_number(&mut self) -> Result<i32> {
-        let sign = match self.advance() {
-            Token::Space => 1,
-            Token::Tab => -1,
-            other => bail!("invalid sign specifier {other:?}"),
+    fn parse_number(&mut self) -> Result<T> {
+        // a literal is a sign and then binary digits up to a LineFeed; the
+        // sign is required, but a sign without digits means 0
+        let start = self.current;
+        let negative = match self.advance()? {
+            Token::Space => false,
+            Token::Tab => true,
+            Token::LineFeed => return Err(self.error("number literal is missing its sign")),
         };
 
-        let mut value = 0;
+        let mut bits = Vec::new();
 
         loop {
-            let token = self.advance();
-            match token {
-                Token::Space => {
-                    value <<= 1;
-                }
-                Token::Tab => {
-                    value <<= 1;
-                    value += 1;
-                }
+            bits.push(match self.advance()? {
+                Token::Space => false,
+                Token::Tab => true,
                 Token::LineFeed => break,
+            });
+
+            if let Some(max) = self.limits.max_number_bits {
+                if bits.len() > max {
+                    return Err(
+                        self.error(format!("number literal exceeds the limit of {max} bits"))
+                    );
+                }
             }
         }
 
-        Ok(value * sign)
+        if let Some(number) = T::from_bits(negative, &bits) {
+            return Ok(number);
+        }
+
+        let overflow = LiteralOverflow {
+            bits: bits.len(),
+            token: start,
+        };
+        match &mut self.overflows {
+            Some(overflows) => {
+                overflows.push(overflow);
+                Ok(T::zero())
+            }
+            None => Err(overflow.into()),
+        }
     }
 
-    fn parse_label(&mut self) -> String {
+    fn parse_label(&mut self) -> Result<String> {
         let mut label = String::new();
 
         loop {
-            let token = self.advance();
-            label.push(match token {
+            label.push(match self.advance()? {
                 Token::Space => ' ',
                 Token::Tab => '\t',
                 Token::LineFeed => break,
             });
+
+            if let Some(max) = self.limits.max_label_length {
+                if label.len() > max {
+                    return Err(self.error(format!("label exceeds the limit of {max} characters")));
+                }
+            }
         }
 
-        label
+        Ok(label)
     }
 }
 
@@ -226,9 +580,8 @@ mod tests {
             Token::LineFeed,
         ];
 
-        let mut parser = Parser::new(tokens);
-        parser.parse().unwrap();
-        let instruction = parser.output.get(0).unwrap();
+        let program: Program = Parser::new(tokens).parse().unwrap();
+        let instruction = program.first().unwrap();
         assert!(matches!(instruction, Instruction::Push(-50)));
     }
 
@@ -250,11 +603,166 @@ mod tests {
             Token::Tab,
         ];
 
-        let mut parser = Parser::new(tokens);
-        parser.parse().unwrap();
-        let first = parser.output.get(0).unwrap();
-        let second = parser.output.get(1).unwrap();
+        let program: Program = Parser::new(tokens).parse().unwrap();
+        let first = program.first().unwrap();
+        let second = program.get(1).unwrap();
         assert!(matches!(first, Instruction::Push(-50)));
         assert!(matches!(second, Instruction::Swap));
     }
+
+    #[test]
+    fn instruction_limit() {
+        let tokens = vec![
+            Token::Space,
+            Token::LineFeed,
+            Token::Space,
+            Token::Space,
+            Token::LineFeed,
+            Token::Space,
+        ];
+        let limits = ParseLimits {
+            max_instructions: Some(1),
+            ..ParseLimits::default()
+        };
+
+        let parser: Parser = Parser::with_limits(tokens, limits);
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn label_and_number_limits() {
+        let label = vec![
+            Token::LineFeed,
+            Token::Space,
+            Token::LineFeed,
+            Token::Space,
+            Token::Tab,
+            Token::LineFeed,
+        ];
+        let number = vec![
+            Token::Space,
+            Token::Space,
+            Token::Space,
+            Token::Tab,
+            Token::Tab,
+            Token::LineFeed,
+        ];
+        let limits = ParseLimits {
+            max_label_length: Some(1),
+            max_number_bits: Some(1),
+            ..ParseLimits::default()
+        };
+
+        assert!(Parser::<i32>::with_limits(label, limits).parse().is_err());
+        assert!(Parser::<i32>::with_limits(number, limits).parse().is_err());
+    }
+
+    #[test]
+    fn zero_literals() {
+        for literal in [
+            vec![Token::Space, Token::LineFeed],
+            vec![Token::Tab, Token::LineFeed],
+            vec![Token::Space, Token::Space, Token::Space, Token::LineFeed],
+        ] {
+            let mut tokens = vec![Token::Space, Token::Space];
+            tokens.extend(literal);
+
+            let program: Program = Parser::new(tokens).parse().unwrap();
+            assert!(matches!(program[..], [Instruction::Push(0)]));
+        }
+    }
+
+    #[test]
+    fn literal_without_sign() {
+        let tokens = vec![
+            Token::Space,
+            Token::Space,
+            Token::Space,
+            Token::LineFeed,
+            Token::Space,
+            Token::Space,
+            Token::LineFeed,
+        ];
+
+        let error = Parser::<i32>::new(tokens).parse().unwrap_err();
+        assert!(matches!(
+            error,
+            WsError::ParseError { offset: 4, ref message } if message.contains("sign")
+        ));
+    }
+
+    #[test]
+    fn unterminated_input() {
+        let number = vec![Token::Space, Token::Space, Token::Space, Token::Tab];
+        let label = vec![Token::LineFeed, Token::Space, Token::Space, Token::Tab];
+        let instruction = vec![Token::Tab, Token::Space];
+
+        for tokens in [number, label, instruction] {
+            let parser: Parser = Parser::new(tokens);
+            let error = parser.parse().unwrap_err();
+            assert!(error.to_string().starts_with("unexpected end of program"));
+        }
+    }
+
+    #[test]
+    fn literal_overflow() {
+        let mut tokens = vec![Token::Space, Token::LineFeed, Token::Space];
+        tokens.extend([Token::Space, Token::Space, Token::Space]);
+        tokens.extend(vec![Token::Tab; 32]);
+        tokens.push(Token::LineFeed);
+
+        let error = Parser::<i32>::new(tokens.clone()).parse().unwrap_err();
+        assert!(matches!(
+            error,
+            WsError::LiteralOverflow(LiteralOverflow { bits: 32, token: 5 })
+        ));
+
+        Parser::<i64>::new(tokens).parse().unwrap();
+    }
+
+    #[test]
+    fn mnemonics() {
+        let instructions: Vec<Instruction> = vec![
+            Instruction::Push(-42),
+            Instruction::MarkLocation(" \t".to_string()),
+            Instruction::JumpIfZero(String::new()),
+            Instruction::OutputNumber,
+        ];
+
+        let text: Vec<String> = instructions.iter().map(ToString::to_string).collect();
+        assert_eq!(text, vec!["push -42", "label @ST", "jz @", "printn"]);
+
+        let parsed: Vec<Instruction> = text.iter().map(|line| line.parse().unwrap()).collect();
+        assert_eq!(parsed, instructions);
+
+        for invalid in ["push", "push x", "jmp ST", "label @SX", "nop"] {
+            assert!(invalid.parse::<Instruction>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_all_problems() {
+        let mut tokens = vec![Token::Space, Token::Space, Token::Space];
+        tokens.extend(vec![Token::Tab; 40]);
+        tokens.extend([
+            Token::LineFeed,
+            Token::LineFeed,
+            Token::Tab,
+            Token::LineFeed,
+        ]);
+        tokens.extend([Token::Tab, Token::Space]);
+
+        let parser: Parser = Parser::new(tokens);
+        let (program, problems) = parser.parse_all();
+
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(problems[0].error, WsError::LiteralOverflow(_)));
+        assert_eq!(problems[0].token, 2);
+        assert_eq!(problems[1].token, 47);
+        assert!(matches!(
+            program[..],
+            [Instruction::Push(0), Instruction::EndSubroutine]
+        ));
+        assert_eq!(program.starts(), [0, 44]);
+    }
 }
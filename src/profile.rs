@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufWriter, Write};
+
+use crate::parser::Instruction;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct HeapAccess {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+/// Per-address heap read/write counts collected during a run.
+#[derive(Debug, Default, Clone)]
+pub struct HeapHeatmap {
+    accesses: BTreeMap<usize, HeapAccess>,
+}
+
+impl HeapHeatmap {
+    pub fn record_read(&mut self, address: usize) {
+        self.accesses.entry(address).or_default().reads += 1;
+    }
+
+    pub fn record_write(&mut self, address: usize) {
+        self.accesses.entry(address).or_default().writes += 1;
+    }
+
+    pub fn get(&self, address: usize) -> HeapAccess {
+        self.accesses.get(&address).copied().unwrap_or_default()
+    }
+
+    /// Writes one `address,reads,writes` row per accessed address.
+    pub fn write_csv(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "address,reads,writes")?;
+
+        for (address, access) in &self.accesses {
+            writeln!(out, "{address},{},{}", access.reads, access.writes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws a grid of `width` addresses per row, each shown by a character
+    /// that gets denser with its reads plus writes. Rows without any access
+    /// are left out.
+    pub fn write_ascii(&self, mut out: impl Write, width: usize) -> io::Result<()> {
+        let width = width.max(1);
+        let total = |access: &HeapAccess| access.reads + access.writes;
+        let busiest = self.accesses.values().map(total).max().unwrap_or(0);
+
+        writeln!(
+            out,
+            "{width} addresses per row, from ' ' (none) to '{}' ({busiest} accesses)",
+            char::from(HEATMAP_RAMP[HEATMAP_RAMP.len() - 1])
+        )?;
+
+        let mut rows: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for (address, access) in &self.accesses {
+            // rounded up, so that any access shows up
+            let level = (total(access) * (HEATMAP_RAMP.len() as u64 - 1)).div_ceil(busiest);
+            let row = rows
+                .entry(address / width)
+                .or_insert_with(|| vec![b' '; width]);
+            row[address % width] = HEATMAP_RAMP[level as usize];
+        }
+
+        let digits = self
+            .accesses
+            .keys()
+            .last()
+            .map_or(1, |last| (last - last % width).to_string().len());
+        for (row, cells) in rows {
+            let cells = String::from_utf8_lossy(&cells);
+            writeln!(out, "{:>digits$} |{cells}|", row * width)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Characters of [`HeapHeatmap::write_ascii`], from no access to the most.
+const HEATMAP_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Stack depth sampled every `interval` executed instructions.
+#[derive(Debug, Clone)]
+pub struct StackTimeline {
+    interval: u64,
+    steps: u64,
+    samples: Vec<(u64, usize)>,
+}
+
+impl StackTimeline {
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            steps: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Records the stack depth before the next instruction executes.
+    pub fn record(&mut self, depth: usize) {
+        if self.steps.is_multiple_of(self.interval) {
+            self.samples.push((self.steps, depth));
+        }
+
+        self.steps += 1;
+    }
+
+    pub fn samples(&self) -> &[(u64, usize)] {
+        &self.samples
+    }
+
+    /// Writes one `step,depth` row per sample.
+    pub fn write_csv(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "step,depth")?;
+
+        for (step, depth) in &self.samples {
+            writeln!(out, "{step},{depth}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of stack items, from the top, shown on each trace line.
+const TRACE_STACK_ITEMS: usize = 4;
+
+/// Writes a line for every executed instruction, up to an optional limit.
+///
+/// Output is buffered; call [`Tracer::flush`] once the program is done.
+pub struct Tracer {
+    out: BufWriter<Box<dyn Write + Send>>,
+    limit: Option<u64>,
+    lines: u64,
+}
+
+impl Tracer {
+    pub fn new(out: impl Write + Send + 'static, limit: Option<u64>) -> Self {
+        Self {
+            out: BufWriter::new(Box::new(out)),
+            limit,
+            lines: 0,
+        }
+    }
+
+    /// Records `instruction`, at index `ip`, about to run on `stack`.
+    pub fn record<T: fmt::Debug + fmt::Display>(
+        &mut self,
+        ip: usize,
+        instruction: &Instruction<T>,
+        stack: &[T],
+    ) -> io::Result<()> {
+        match self.limit {
+            Some(limit) if self.lines > limit => return Ok(()),
+            Some(limit) if self.lines == limit => {
+                writeln!(self.out, "trace truncated after {limit} instructions")?;
+            }
+            _ => writeln!(
+                self.out,
+                "{}",
+                trace_line(self.lines, ip, instruction, stack)
+            )?,
+        }
+
+        self.lines += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("limit", &self.limit)
+            .field("lines", &self.lines)
+            .finish_non_exhaustive()
+    }
+}
+
+fn trace_line<T: fmt::Debug + fmt::Display>(
+    step: u64,
+    ip: usize,
+    instruction: &Instruction<T>,
+    stack: &[T],
+) -> String {
+    let hidden = stack.len().saturating_sub(TRACE_STACK_ITEMS);
+    let mut top: Vec<String> = stack[hidden..].iter().map(ToString::to_string).collect();
+    if hidden > 0 {
+        top.insert(0, "...".to_string());
+    }
+
+    format!(
+        "step {step}, ip {ip}: {instruction:?}, stack [{}]",
+        top.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heatmap_csv() {
+        let mut heatmap = HeapHeatmap::default();
+        heatmap.record_write(10);
+        heatmap.record_read(10);
+        heatmap.record_read(10);
+        heatmap.record_read(2);
+
+        let mut out = Vec::new();
+        heatmap.write_csv(&mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "address,reads,writes\n2,1,0\n10,2,1\n"
+        );
+    }
+
+    #[test]
+    fn heatmap_ascii() {
+        let mut heatmap = HeapHeatmap::default();
+        for _ in 0..9 {
+            heatmap.record_read(1);
+        }
+        heatmap.record_write(3);
+        heatmap.record_write(21);
+
+        let mut out = Vec::new();
+        heatmap.write_ascii(&mut out, 8).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "8 addresses per row, from ' ' (none) to '@' (9 accesses)\n",
+                " 0 | @ .    |\n",
+                "16 |     .  |\n",
+            )
+        );
+    }
+
+    #[test]
+    fn timeline_interval() {
+        let mut timeline = StackTimeline::new(2);
+        for depth in [0, 1, 2, 1, 0] {
+            timeline.record(depth);
+        }
+
+        assert_eq!(timeline.samples(), &[(0, 0), (2, 2), (4, 0)]);
+    }
+
+    #[test]
+    fn trace_lines() {
+        assert_eq!(
+            trace_line(0, 3, &Instruction::Push(1), &[]),
+            "step 0, ip 3: Push(1), stack []"
+        );
+        assert_eq!(
+            trace_line::<i32>(7, 1, &Instruction::Add, &[1, 2, 3, 4, 5]),
+            "step 7, ip 1: Add, stack [..., 2, 3, 4, 5]"
+        );
+    }
+}
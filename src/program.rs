@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+
+use crate::error::Result;
+use crate::parser::Instruction;
+use crate::validator;
+
+/// A parsed program, ready to be run by a [`VM`](crate::VM).
+///
+/// Derefs to the slice of its instructions, so it can be passed wherever a
+/// `&[Instruction<T>]` is expected.
+///
+/// With the `serde` feature, a program serializes as its instructions and
+/// their token offsets, which may be left out when deserializing.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program<T = i32> {
+    instructions: Vec<Instruction<T>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    starts: Vec<usize>,
+}
+
+impl<T> Program<T> {
+    /// Builds a program one instruction at a time from Rust code.
+    pub fn builder() -> ProgramBuilder<T> {
+        ProgramBuilder::new()
+    }
+
+    pub fn new(instructions: Vec<Instruction<T>>) -> Self {
+        Self {
+            instructions,
+            starts: Vec::new(),
+        }
+    }
+
+    /// A program whose instruction `i` starts at token `starts[i]`.
+    pub(crate) fn with_starts(instructions: Vec<Instruction<T>>, starts: Vec<usize>) -> Self {
+        Self {
+            instructions,
+            starts,
+        }
+    }
+
+    pub fn instructions(&self) -> &[Instruction<T>] {
+        &self.instructions
+    }
+
+    pub fn into_instructions(self) -> Vec<Instruction<T>> {
+        self.instructions
+    }
+
+    /// Index of the first token of each instruction; empty if the program
+    /// wasn't parsed from tokens.
+    pub fn starts(&self) -> &[usize] {
+        &self.starts
+    }
+
+    /// Index of the MarkLocation of every label; the first one if a label is
+    /// marked more than once.
+    pub fn labels(&self) -> HashMap<&str, usize> {
+        let mut labels = HashMap::new();
+
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            if let Instruction::MarkLocation(label) = instruction {
+                labels.entry(label.as_str()).or_insert(i);
+            }
+        }
+
+        labels
+    }
+
+    /// Checks that every label jumped to is marked exactly once.
+    pub fn validate(&self) -> Result<()> {
+        validator::validate(&self.instructions)
+    }
+}
+
+impl<T> Default for Program<T> {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<T> Deref for Program<T> {
+    type Target = [Instruction<T>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.instructions
+    }
+}
+
+impl<T> From<Vec<Instruction<T>>> for Program<T> {
+    fn from(instructions: Vec<Instruction<T>>) -> Self {
+        Self::new(instructions)
+    }
+}
+
+impl<T> FromIterator<Instruction<T>> for Program<T> {
+    fn from_iter<I: IntoIterator<Item = Instruction<T>>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Program<T> {
+    type Item = &'a Instruction<T>;
+    type IntoIter = std::slice::Iter<'a, Instruction<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.instructions.iter()
+    }
+}
+
+/// One instruction mnemonic per line.
+impl<T: fmt::Display> fmt::Display for Program<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{instruction}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds a [`Program`] with one method per instruction mnemonic, e.g. for
+/// code generators and tests.
+///
+/// Labels are given readable names, which are turned into distinct labels of
+/// spaces and tabs in the order they first appear.
+///
+/// ```
+/// use whitespace::Program;
+///
+/// let program: Program = Program::builder()
+///     .push(5)
+///     .label("loop")
+///     .dup()
+///     .printn()
+///     .push(1)
+///     .sub()
+///     .dup()
+///     .jz("done")
+///     .jmp("loop")
+///     .label("done")
+///     .end()
+///     .build();
+/// assert_eq!(program.len(), 11);
+/// ```
+#[derive(Debug)]
+pub struct ProgramBuilder<T = i32> {
+    instructions: Vec<Instruction<T>>,
+    labels: HashMap<String, String>,
+}
+
+impl<T> ProgramBuilder<T> {
+    pub fn new() -> Self {
+        Self {
+            instructions: Vec::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    pub fn instruction(mut self, instruction: Instruction<T>) -> Self {
+        self.instructions.push(instruction);
+        self
+    }
+
+    /// The label of spaces and tabs standing for `name`.
+    fn label_for(&mut self, name: &str) -> String {
+        let next = self.labels.len();
+
+        self.labels
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                format!("{next:b}")
+                    .chars()
+                    .map(|bit| if bit == '1' { '\t' } else { ' ' })
+                    .collect()
+            })
+            .clone()
+    }
+
+    pub fn push(self, number: T) -> Self {
+        self.instruction(Instruction::Push(number))
+    }
+
+    pub fn dup(self) -> Self {
+        self.instruction(Instruction::Duplicate)
+    }
+
+    pub fn copy(self, index: T) -> Self {
+        self.instruction(Instruction::Copy(index))
+    }
+
+    pub fn swap(self) -> Self {
+        self.instruction(Instruction::Swap)
+    }
+
+    pub fn drop(self) -> Self {
+        self.instruction(Instruction::Discard)
+    }
+
+    pub fn slide(self, count: T) -> Self {
+        self.instruction(Instruction::Slide(count))
+    }
+
+    pub fn add(self) -> Self {
+        self.instruction(Instruction::Add)
+    }
+
+    pub fn sub(self) -> Self {
+        self.instruction(Instruction::Substract)
+    }
+
+    pub fn mul(self) -> Self {
+        self.instruction(Instruction::Multiply)
+    }
+
+    pub fn div(self) -> Self {
+        self.instruction(Instruction::Divide)
+    }
+
+    /// The `mod` instruction.
+    pub fn modulo(self) -> Self {
+        self.instruction(Instruction::Modulo)
+    }
+
+    pub fn store(self) -> Self {
+        self.instruction(Instruction::HeapStore)
+    }
+
+    pub fn retrieve(self) -> Self {
+        self.instruction(Instruction::HeapRetrieve)
+    }
+
+    pub fn label(mut self, name: &str) -> Self {
+        let label = self.label_for(name);
+        self.instruction(Instruction::MarkLocation(label))
+    }
+
+    pub fn call(mut self, name: &str) -> Self {
+        let label = self.label_for(name);
+        self.instruction(Instruction::Call(label))
+    }
+
+    pub fn jmp(mut self, name: &str) -> Self {
+        let label = self.label_for(name);
+        self.instruction(Instruction::Jump(label))
+    }
+
+    pub fn jz(mut self, name: &str) -> Self {
+        let label = self.label_for(name);
+        self.instruction(Instruction::JumpIfZero(label))
+    }
+
+    pub fn jn(mut self, name: &str) -> Self {
+        let label = self.label_for(name);
+        self.instruction(Instruction::JumpIfNegative(label))
+    }
+
+    pub fn ret(self) -> Self {
+        self.instruction(Instruction::EndSubroutine)
+    }
+
+    pub fn end(self) -> Self {
+        self.instruction(Instruction::EndProgram)
+    }
+
+    pub fn printc(self) -> Self {
+        self.instruction(Instruction::OutputChar)
+    }
+
+    pub fn printn(self) -> Self {
+        self.instruction(Instruction::OutputNumber)
+    }
+
+    pub fn readc(self) -> Self {
+        self.instruction(Instruction::ReadChar)
+    }
+
+    pub fn readn(self) -> Self {
+        self.instruction(Instruction::ReadNumber)
+    }
+
+    pub fn build(self) -> Program<T> {
+        Program::new(self.instructions)
+    }
+}
+
+impl<T> Default for ProgramBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a [`Program`] from a list of instruction mnemonics, with arguments
+/// in parentheses and labels written as plain names.
+///
+/// An argument that is a single identifier is taken as a label name, so
+/// numbers held in variables need braces: `push({ n })`.
+///
+/// ```
+/// let program: whitespace::Program = whitespace::ws_program![
+///     push(3), label(again), dup, printn, push(1), sub, dup, jz(done), jmp(again),
+///     label(done), end,
+/// ];
+/// assert_eq!(program.len(), 11);
+/// ```
+#[macro_export]
+macro_rules! ws_program {
+    ($($op:ident $(($($argument:tt)*))?),* $(,)?) => {{
+        let builder = $crate::program::ProgramBuilder::new();
+        $(let builder = $crate::ws_program!(@op builder, $op $(($($argument)*))?);)*
+        builder.build()
+    }};
+    (@op $builder:ident, mod) => {
+        $builder.modulo()
+    };
+    (@op $builder:ident, $op:ident ($label:ident)) => {
+        $builder.$op(stringify!($label))
+    };
+    (@op $builder:ident, $op:ident ($number:expr)) => {
+        $builder.$op($number)
+    };
+    (@op $builder:ident, $op:ident) => {
+        $builder.$op()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn helpers() {
+        let program: Program = vec![
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::Push(1),
+            Instruction::Jump(" ".to_string()),
+        ]
+        .into();
+
+        assert_eq!(program.len(), 3);
+        assert!(matches!(program.get(1), Some(Instruction::Push(1))));
+        assert_eq!(program.labels(), HashMap::from([(" ", 0)]));
+        assert!(program.validate().is_ok());
+        assert_eq!(program.to_string(), "label @S\npush 1\njmp @S\n");
+    }
+
+    #[test]
+    fn builder() {
+        let built: Program = Program::builder()
+            .push(10)
+            .push(3)
+            .modulo()
+            .call("print")
+            .end()
+            .label("print")
+            .printn()
+            .ret()
+            .build();
+        let expanded: Program = crate::ws_program![
+            push(10),
+            push(3),
+            mod,
+            call(print),
+            end,
+            label(print),
+            printn,
+            ret,
+        ];
+
+        assert_eq!(built, expanded);
+        assert_eq!(built.labels(), HashMap::from([(" ", 5)]));
+        assert!(built.validate().is_ok());
+
+        let labels: Program = ws_program![label(a), label(b), label(c), jmp(b)];
+        assert_eq!(
+            labels.to_string(),
+            "label @S\nlabel @T\nlabel @TS\njmp @T\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let program: Program =
+            vec![Instruction::Push(-3), Instruction::Call("\t".to_string())].into();
+
+        let json = serde_json::to_string(&program).unwrap();
+        assert_eq!(
+            json,
+            r#"{"instructions":[{"Push":-3},{"Call":"\t"}],"starts":[]}"#
+        );
+        assert_eq!(serde_json::from_str::<Program>(&json).unwrap(), program);
+
+        let bare: Program = serde_json::from_str(r#"{"instructions":["EndProgram"]}"#).unwrap();
+        assert!(matches!(bare[..], [Instruction::EndProgram]));
+    }
+}
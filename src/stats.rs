@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+use crate::parser::Instruction;
+
+/// Measurements of a program taken without running it.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub tokens: usize,
+    pub instructions: usize,
+    /// Number of instructions of each kind, by [`Instruction::name`].
+    pub histogram: BTreeMap<&'static str, usize>,
+    /// Bytes taken by the instructions of each kind.
+    pub bytes: BTreeMap<&'static str, usize>,
+    /// Number of marked labels.
+    pub labels: usize,
+    /// Number of number literals of each size, in bits.
+    pub literal_bits: BTreeMap<usize, usize>,
+}
+
+/// Measures a program of `tokens` tokens, given the index of the first token
+/// of each instruction.
+pub fn collect<T>(instructions: &[Instruction<T>], starts: &[usize], tokens: usize) -> Stats {
+    let mut stats = Stats {
+        tokens,
+        instructions: instructions.len(),
+        ..Stats::default()
+    };
+
+    let sizes = instruction_bytes(starts, tokens);
+    for (i, instruction) in instructions.iter().enumerate() {
+        *stats.histogram.entry(instruction.name()).or_default() += 1;
+        *stats.bytes.entry(instruction.name()).or_default() += sizes[i];
+
+        match instruction {
+            Instruction::MarkLocation(_) => stats.labels += 1,
+            Instruction::Push(_) | Instruction::Copy(_) | Instruction::Slide(_) => {
+                // the literal is what follows the opcode, less its sign and
+                // line feed
+                let bits = sizes[i].saturating_sub(instruction.opcode_len() + 2);
+                *stats.literal_bits.entry(bits).or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+
+    stats
+}
+
+/// Bytes each instruction takes in the whitespace encoding, one token being
+/// one byte.
+pub fn instruction_bytes(starts: &[usize], tokens: usize) -> Vec<usize> {
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, start)| starts.get(i + 1).copied().unwrap_or(tokens) - start)
+        .collect()
+}
+
+/// The code from one label to the next.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Section {
+    /// `None` for the code before the first label.
+    pub label: Option<String>,
+    /// Index of the first instruction.
+    pub start: usize,
+    pub instructions: usize,
+    pub bytes: usize,
+}
+
+/// Splits the program at each marked label, largest section first.
+pub fn sections<T>(
+    instructions: &[Instruction<T>],
+    starts: &[usize],
+    tokens: usize,
+) -> Vec<Section> {
+    let sizes = instruction_bytes(starts, tokens);
+    let mut sections: Vec<Section> = Vec::new();
+
+    for (i, instruction) in instructions.iter().enumerate() {
+        let label = match instruction {
+            Instruction::MarkLocation(label) => Some(label.clone()),
+            _ => None,
+        };
+        if label.is_some() || sections.is_empty() {
+            sections.push(Section {
+                label,
+                start: i,
+                instructions: 0,
+                bytes: 0,
+            });
+        }
+
+        if let Some(section) = sections.last_mut() {
+            section.instructions += 1;
+            section.bytes += sizes[i];
+        }
+    }
+
+    sections.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.start.cmp(&b.start)));
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{letters_to_whitespace, Lexer};
+    use crate::parser::Parser;
+    use crate::program::Program;
+
+    #[test]
+    fn histogram() {
+        let source = letters_to_whitespace("SSSTL SSSTSL STSSL LSSTL TLST LLL");
+        let tokens = Lexer::new(source).lex();
+        let program: Program = Parser::new(tokens.clone()).parse().unwrap();
+
+        let stats = collect(&program, program.starts(), tokens.len());
+
+        assert_eq!(stats.tokens, 28);
+        assert_eq!(stats.instructions, 6);
+        assert_eq!(stats.labels, 1);
+        assert_eq!(stats.histogram["Push"], 2);
+        assert_eq!(stats.histogram["Copy"], 1);
+        assert_eq!(stats.histogram.get("Add"), None);
+        assert_eq!(stats.literal_bits, BTreeMap::from([(0, 1), (1, 1), (2, 1)]));
+        assert_eq!(stats.bytes["Push"], 11);
+        assert_eq!(stats.bytes["MarkLocation"], 5);
+
+        let sections = sections(&program, program.starts(), tokens.len());
+        let found: Vec<(Option<&str>, usize, usize)> = sections
+            .iter()
+            .map(|section| {
+                (
+                    section.label.as_deref(),
+                    section.instructions,
+                    section.bytes,
+                )
+            })
+            .collect();
+        assert_eq!(found, vec![(None, 3, 16), (Some("\t"), 3, 12)]);
+    }
+}
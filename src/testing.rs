@@ -0,0 +1,142 @@
+//! Running Whitespace programs from `#[test]` functions, with scripted input
+//! and captured output, without spawning the interpreter.
+//!
+//! ```
+//! use whitespace::lexer::letters_to_whitespace;
+//!
+//! // read a number and print it twice
+//! let source = letters_to_whitespace("SSSL TLTT SSSL TTT SLS TLST TLST LLL");
+//!
+//! let result = whitespace::testing::run(&source, "21\n");
+//! result.assert_success().assert_stdout("2121");
+//! ```
+
+use std::error::Error;
+use std::io;
+
+use crate::cell::WhitespaceInt;
+use crate::error::WsError;
+use crate::interpreter::{Streams, VM};
+use crate::program::Program;
+
+/// Steps after which [`run`] gives up, so that a program stuck in a loop fails
+/// the test instead of hanging it.
+pub const DEFAULT_FUEL: u64 = 1_000_000;
+
+/// What a program did when run by [`run`] or [`run_program`].
+#[derive(Debug)]
+pub struct RunResult<T = i32> {
+    /// Everything the program printed, up to the error if it failed.
+    pub stdout: String,
+    /// Why the program stopped early, if it did; parse errors included.
+    pub error: Option<WsError>,
+    /// See [`VM::exit_code`].
+    pub exit_code: Option<T>,
+    pub stack: Vec<T>,
+}
+
+impl<T> RunResult<T> {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Panics with the error and the output so far if the program failed.
+    #[track_caller]
+    pub fn assert_success(&self) -> &Self {
+        if let Some(error) = &self.error {
+            // show the io::Error behind an IoError, not just its context
+            let mut message = error.to_string();
+            let mut source = error.source();
+            while let Some(cause) = source {
+                message.push_str(&format!(": {cause}"));
+                source = cause.source();
+            }
+
+            panic!(
+                "program failed: {message}\noutput so far: {:?}",
+                self.stdout
+            );
+        }
+
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_stdout(&self, expected: &str) -> &Self {
+        assert_eq!(self.stdout, expected, "unexpected program output");
+        self
+    }
+}
+
+/// Parses and runs Whitespace source text on a fresh [`VM`] with 32-bit cells,
+/// feeding it `stdin` and stopping after [`DEFAULT_FUEL`] steps.
+pub fn run(source: &str, stdin: &str) -> RunResult {
+    match crate::parse(source) {
+        Ok(program) => run_program(&program, stdin),
+        Err(error) => RunResult {
+            stdout: String::new(),
+            error: Some(error),
+            exit_code: None,
+            stack: Vec::new(),
+        },
+    }
+}
+
+/// Like [`run`], for a program that is already parsed or was built in Rust.
+pub fn run_program<T: WhitespaceInt>(program: &Program<T>, stdin: &str) -> RunResult<T> {
+    run_with(VM::new(), program, stdin)
+}
+
+/// Like [`run_program`], on a VM set up by the caller, e.g. with other limits
+/// or arithmetic settings.
+pub fn run_with<T: WhitespaceInt>(
+    mut vm: VM<T>,
+    program: &Program<T>,
+    stdin: &str,
+) -> RunResult<T> {
+    vm.host_io = Box::new(Streams::new(io::Cursor::new(stdin.to_string()), io::sink()));
+    vm.capture_output();
+
+    let error = vm.execute_with_fuel(program, DEFAULT_FUEL).err();
+
+    RunResult {
+        stdout: vm.captured_output().unwrap_or_default().to_string(),
+        error,
+        exit_code: vm.exit_code.clone(),
+        stack: vm.stack,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::EXAMPLES;
+    use crate::lexer::letters_to_whitespace;
+
+    #[test]
+    fn examples() {
+        let hello = letters_to_whitespace(EXAMPLES[0].source);
+        run(&hello, "")
+            .assert_success()
+            .assert_stdout("Hello, world!\n");
+
+        let cat = letters_to_whitespace(EXAMPLES[1].source);
+        let result = run(&cat, "meow");
+        assert_eq!(result.stdout, "meow");
+    }
+
+    #[test]
+    fn failures() {
+        let result = run(" \t", "");
+        assert!(matches!(result.error, Some(WsError::ParseError { .. })));
+
+        let program: Program = crate::ws_program![push(72), printc, drop];
+        let result = run_program(&program, "");
+        assert!(!result.is_success());
+        assert_eq!(result.stdout, "H");
+
+        let spin: Program = crate::ws_program![label(spin), jmp(spin)];
+        let result = run_program(&spin, "");
+        assert!(matches!(result.error, Some(WsError::FuelExhausted(_))));
+    }
+}
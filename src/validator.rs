@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use crate::diagnostics::Code;
+use crate::error::{Result, WsError};
+use crate::parser::Instruction;
+
+/// A reason the program cannot run, found by [`problems`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub instruction: usize,
+    pub code: Code,
+    pub label: String,
+    pub message: String,
+}
+
+/// A label problem found by [`scan`], the single pass behind [`problems`] and
+/// the `check_*` functions.
+enum Finding<'a> {
+    Duplicate {
+        label: &'a String,
+        first: usize,
+        second: usize,
+    },
+    Undefined {
+        label: &'a String,
+        instruction: usize,
+    },
+}
+
+impl Finding<'_> {
+    fn instruction(&self) -> usize {
+        match self {
+            Finding::Duplicate { second, .. } => *second,
+            Finding::Undefined { instruction, .. } => *instruction,
+        }
+    }
+}
+
+// one pass for both kinds of problem, sorted by instruction
+fn scan<T>(instructions: &[Instruction<T>]) -> Vec<Finding<'_>> {
+    let mut defined: HashMap<&String, usize> = HashMap::new();
+    let mut findings = Vec::new();
+
+    for (i, instruction) in instructions.iter().enumerate() {
+        if let Instruction::MarkLocation(label) = instruction {
+            match defined.get(label) {
+                Some(&first) => findings.push(Finding::Duplicate {
+                    label,
+                    first,
+                    second: i,
+                }),
+                None => {
+                    defined.insert(label, i);
+                }
+            }
+        }
+    }
+
+    for (i, instruction) in instructions.iter().enumerate() {
+        if let Instruction::Call(label)
+        | Instruction::Jump(label)
+        | Instruction::JumpIfZero(label)
+        | Instruction::JumpIfNegative(label) = instruction
+        {
+            if !defined.contains_key(label) {
+                findings.push(Finding::Undefined {
+                    label,
+                    instruction: i,
+                });
+            }
+        }
+    }
+
+    findings.sort_by_key(Finding::instruction);
+    findings
+}
+
+/// Every duplicate label and reference to an undefined label, in program
+/// order.
+pub fn problems<T>(instructions: &[Instruction<T>]) -> Vec<Problem> {
+    scan(instructions)
+        .into_iter()
+        .map(|finding| match finding {
+            Finding::Duplicate {
+                label,
+                first,
+                second,
+            } => Problem {
+                instruction: second,
+                code: Code::DuplicateLabel,
+                label: label.clone(),
+                message: format!("label {label:?} is already marked at instruction {first}"),
+            },
+            Finding::Undefined { label, instruction } => Problem {
+                instruction,
+                code: Code::UndefinedLabel,
+                label: label.clone(),
+                message: format!("label {label:?} is never marked"),
+            },
+        })
+        .collect()
+}
+
+/// Rejects programs that parse but cannot run correctly.
+pub fn validate<T>(instructions: &[Instruction<T>]) -> Result<()> {
+    check_duplicate_labels(instructions)?;
+    check_undefined_labels(instructions)
+}
+
+/// Fails if a label is marked more than once.
+pub fn check_duplicate_labels<T>(instructions: &[Instruction<T>]) -> Result<()> {
+    let duplicate = scan(instructions)
+        .into_iter()
+        .find_map(|finding| match finding {
+            Finding::Duplicate {
+                label,
+                first,
+                second,
+            } => Some(WsError::DuplicateLabel {
+                label: label.clone(),
+                first,
+                second,
+            }),
+            Finding::Undefined { .. } => None,
+        });
+
+    match duplicate {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Fails if any Call or Jump targets a label that is never marked, listing
+/// every such reference.
+pub fn check_undefined_labels<T>(instructions: &[Instruction<T>]) -> Result<()> {
+    let undefined: Vec<String> = scan(instructions)
+        .into_iter()
+        .filter_map(|finding| match finding {
+            Finding::Undefined { label, instruction } => {
+                Some(format!("{label:?} at instruction {instruction}"))
+            }
+            Finding::Duplicate { .. } => None,
+        })
+        .collect();
+
+    if !undefined.is_empty() {
+        return Err(WsError::UndefinedLabels(undefined));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_labels() {
+        let program: Vec<Instruction> = vec![
+            Instruction::MarkLocation(" ".to_string()),
+            Instruction::MarkLocation("\t".to_string()),
+            Instruction::MarkLocation(" ".to_string()),
+        ];
+
+        assert_eq!(
+            validate(&program).unwrap_err().to_string(),
+            "label \" \" is marked at both instruction 0 and instruction 2"
+        );
+        assert!(validate(&program[..2]).is_ok());
+    }
+
+    #[test]
+    fn undefined_labels() {
+        let program: Vec<Instruction> = vec![
+            Instruction::Jump(" ".to_string()),
+            Instruction::MarkLocation("\t".to_string()),
+            Instruction::Call("\t".to_string()),
+            Instruction::JumpIfZero("  ".to_string()),
+            Instruction::EndProgram,
+        ];
+
+        assert_eq!(
+            validate(&program).unwrap_err().to_string(),
+            "undefined labels: \" \" at instruction 0, \"  \" at instruction 3"
+        );
+    }
+
+    #[test]
+    fn all_problems() {
+        let program: Vec<Instruction> = vec![
+            Instruction::Jump(" ".to_string()),
+            Instruction::MarkLocation("\t".to_string()),
+            Instruction::MarkLocation("\t".to_string()),
+            Instruction::Call("  ".to_string()),
+        ];
+
+        let found: Vec<(usize, Code)> = problems(&program)
+            .iter()
+            .map(|problem| (problem.instruction, problem.code))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, Code::UndefinedLabel),
+                (2, Code::DuplicateLabel),
+                (3, Code::UndefinedLabel)
+            ]
+        );
+    }
+}
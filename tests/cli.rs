@@ -0,0 +1,211 @@
+//! Runs the `whitespace` binary the way a user would.
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use predicates::str::contains;
+use tempfile::TempDir;
+
+use whitespace::lexer::letters_to_whitespace;
+
+/// Writes `letters` as a Whitespace program named `name` in `dir`.
+fn program(dir: &TempDir, name: &str, letters: &str) -> std::path::PathBuf {
+    let path = dir.path().join(name);
+    std::fs::write(&path, letters_to_whitespace(letters)).unwrap();
+    path
+}
+
+fn whitespace() -> Command {
+    Command::cargo_bin("whitespace").unwrap()
+}
+
+/// Prints "Hi".
+const HI: &str = "SSSTSSTSSSL TLSS SSSTTSTSSTL TLSS LLL";
+
+#[test]
+fn run_and_exit_codes() {
+    let dir = TempDir::new().unwrap();
+    let hi = program(&dir, "hi.ws", HI);
+    let three = program(&dir, "three.ws", "SSSTTL LLL");
+    let underflow = program(&dir, "underflow.ws", "TLSS LLL");
+
+    whitespace()
+        .arg("run")
+        .arg(&hi)
+        .assert()
+        .success()
+        .stdout("Hi");
+    // a program path alone is short for `run`
+    whitespace().arg(&hi).assert().success().stdout("Hi");
+
+    whitespace().arg("run").arg(&three).assert().code(0);
+    whitespace()
+        .args(["run", "--exit-code-from-stack"])
+        .arg(&three)
+        .assert()
+        .code(3);
+
+    whitespace()
+        .arg("run")
+        .arg(&underflow)
+        .assert()
+        .code(1)
+        .stderr(contains("stack underflow"));
+}
+
+#[test]
+fn run_all() {
+    let dir = TempDir::new().unwrap();
+    program(&dir, "hi.ws", HI);
+    program(&dir, "underflow.ws", "TLSS LLL");
+
+    whitespace()
+        .arg("run-all")
+        .arg(dir.path())
+        .assert()
+        .code(1)
+        .stdout(contains("1 passed, 1 failed"));
+}
+
+#[test]
+fn fuzz() {
+    whitespace()
+        .args(["fuzz", "--seed", "1", "--runs", "200"])
+        .assert()
+        .success()
+        .stdout(contains("200 passed, 0 failed"));
+    whitespace()
+        .args(["fuzz", "--replay", "1"])
+        .assert()
+        .success()
+        .stdout(contains("0: ").and(contains("steps")));
+}
+
+#[test]
+fn check() {
+    let dir = TempDir::new().unwrap();
+    let hi = program(&dir, "hi.ws", HI);
+    let undefined = program(&dir, "undefined.ws", "LSLTL LLL");
+
+    whitespace().arg("check").arg(&hi).assert().success();
+    whitespace()
+        .args(["check", "--error-format", "json"])
+        .arg(&undefined)
+        .assert()
+        .code(1)
+        .stderr(contains(r#""code":"undefined-label""#));
+}
+
+#[test]
+fn disasm_and_stats() {
+    let dir = TempDir::new().unwrap();
+    let hi = program(&dir, "hi.ws", HI);
+
+    whitespace()
+        .arg("disasm")
+        .arg(&hi)
+        .assert()
+        .success()
+        .stdout("0: push 72\n1: printc\n2: push 105\n3: printc\n4: end\n");
+    whitespace()
+        .arg("stats")
+        .arg(&hi)
+        .assert()
+        .success()
+        .stdout(contains("instructions  5"));
+}
+
+#[test]
+fn fmt_and_asm() {
+    let dir = TempDir::new().unwrap();
+    let commented = dir.path().join("commented.ws");
+    std::fs::write(&commented, "push   \t\nend\n\n\n").unwrap();
+
+    whitespace()
+        .arg("fmt")
+        .arg(&commented)
+        .assert()
+        .success()
+        .stdout("   \t\n\n\n\n");
+
+    whitespace()
+        .arg("asm")
+        .write_stdin("SS ST L  (push 1)")
+        .assert()
+        .success()
+        .stdout("   \t\n");
+    whitespace()
+        .arg("asm")
+        .write_stdin("LSSSL LLL")
+        .assert()
+        .success()
+        .stderr(contains("unused-labels"));
+}
+
+#[test]
+fn heap_init() {
+    let dir = TempDir::new().unwrap();
+    // print the character at heap address 1
+    let peek = program(&dir, "peek.ws", "SSSTL TTT TLSS LLL");
+    let bytes = dir.path().join("bytes.bin");
+    std::fs::write(&bytes, "AB").unwrap();
+
+    whitespace()
+        .args(["run", "--heap-init"])
+        .args([&bytes, &peek])
+        .assert()
+        .success()
+        .stdout("B");
+}
+
+#[test]
+fn resume_keeps_preloaded_heap() {
+    let dir = TempDir::new().unwrap();
+    let spin = program(&dir, "spin.ws", "LSS SL LSL SL");
+    let (first, second) = (dir.path().join("first.bin"), dir.path().join("second.bin"));
+    std::fs::write(&first, "A").unwrap();
+    std::fs::write(&second, "B").unwrap();
+    let (state, resumed) = (dir.path().join("state"), dir.path().join("resumed"));
+
+    whitespace()
+        .args(["run", "--fuel", "10", "--heap-init"])
+        .args([&first, &spin])
+        .arg("--checkpoint")
+        .arg(&state)
+        .assert()
+        .code(130);
+    whitespace()
+        .arg("resume")
+        .args([&state, &spin])
+        .args(["--fuel", "10", "--heap-init"])
+        .arg(&second)
+        .arg("--checkpoint")
+        .arg(&resumed)
+        .assert()
+        .code(130);
+
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&resumed).unwrap()).unwrap();
+    assert_eq!(saved["vm"]["heap"]["dense"][0], 65);
+    assert_eq!(saved["vm"]["steps"], 20);
+}
+
+#[test]
+fn resume_checks_program() {
+    let dir = TempDir::new().unwrap();
+    let spin = program(&dir, "spin.ws", "LSS SL LSL SL");
+    let hi = program(&dir, "hi.ws", HI);
+    let state = dir.path().join("state");
+
+    whitespace()
+        .args(["run", "--fuel", "10", "--checkpoint"])
+        .args([&state, &spin])
+        .assert()
+        .code(130)
+        .stderr(contains("fuel exhausted after 10 steps, state saved"));
+    whitespace()
+        .arg("resume")
+        .args([&state, &hi])
+        .assert()
+        .code(1)
+        .stderr(contains("saved from another program"));
+}